@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use flate2::read::GzDecoder;
+use tauri::Window;
+
+const RESERVED_WINDOWS_NAMES : [&'static str; 22] = ["con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8", "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9"];
+const INVALID_NAME_CHARS : [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+const S_IFMT : u32 = 0o170000;
+const S_IFLNK : u32 = 0o120000;
+const S_IFREG : u32 = 0o100000;
+const S_IFDIR : u32 = 0o040000;
+const FILE_ATTRIBUTE_REPARSE_POINT : u32 = 0x400;
+const FILE_ATTRIBUTE_UNIX_EXTENSION : u32 = 0x8000;
+/// Enough compressed data to decompress the first header of a tar archive
+const MAGIC_BYTES_SIZE : u64 = 4096;
+const TAR_BLOCK_SIZE : usize = 512;
+/// Files and folders, that are only found directly in the game folder of a modded game
+const GAME_ROOT_FOLDERS : [&'static str; 1] = ["bepinex"];
+const GAME_ROOT_FILES : [&'static str; 2] = ["winhttp.dll", "doorstop_config.ini"];
+
+/// Checks a single path component (file or folder name), so that it is valid on every platform
+pub fn check_name(name : &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(format!("\"{}\" is not a valid name", name));
+    }
+    if let Some(c) = name.chars().find(|c| INVALID_NAME_CHARS.contains(c) || *c == '/' || *c == '\\' || c.is_control()) {
+        return Err(format!("\"{}\" contains the invalid character {:?}", name, c));
+    }
+    // Windows ignores everything after the first dot and trailing spaces when checking for device names
+    let stem = name.split('.').next().unwrap().trim_end().to_lowercase();
+    if RESERVED_WINDOWS_NAMES.contains(&stem.as_str()) {
+        return Err(format!("\"{}\" is a reserved name on Windows", name));
+    }
+    Ok(())
+}
+
+/// Converts an untrusted relative path (like an archive entry name) into a path, that cannot leave the folder it is joined onto
+pub fn check_relative_path(path_string : &str) -> Result<PathBuf, String> {
+    let normalized = path_string.replace('\\', "/");
+    if normalized.starts_with('/') {
+        return Err(format!("\"{}\" is an absolute path", path_string));
+    }
+    let mut path = PathBuf::new();
+    for part in normalized.split('/').filter(|p| !p.is_empty() && *p != ".") {
+        if part == ".." {
+            return Err(format!("\"{}\" points outside of its folder", path_string));
+        }
+        check_name(part).map_err(|e| format!("\"{}\" is invalid: {}", path_string, e))?;
+        path.push(part);
+    }
+    if path.as_os_str().is_empty() {
+        return Err(format!("\"{}\" is an empty path", path_string));
+    }
+    // Should always hold after the checks above, but make sure that nothing like a prefix got through
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!("\"{}\" is not a plain relative path", path_string));
+    }
+    Ok(path)
+}
+
+/// Joins an untrusted relative path onto `base`, failing if the result would be outside of `base`
+pub fn safe_join(base : &Path, path_string : &str) -> Result<PathBuf, String> {
+    Ok(base.join(check_relative_path(path_string)?))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    SevenZip,
+    TarGz,
+    Rar
+}
+
+impl ArchiveFormat {
+    /// Detects the archive format from the magic bytes at the start of the file.
+    /// The extension and the GitHub content type are ignored, because they are often wrong.
+    pub fn detect(path : &Path) -> Option<Self> {
+        let mut header = vec![];
+        let file = std::fs::File::open(path).ok()?;
+        file.take(MAGIC_BYTES_SIZE).read_to_end(&mut header).ok()?;
+        Self::from_magic_bytes(&header)
+    }
+
+    pub fn from_magic_bytes(header : &[u8]) -> Option<Self> {
+        if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        } else if header.starts_with(b"7z\xBC\xAF\x27\x1C") {
+            Some(Self::SevenZip)
+        } else if header.starts_with(b"\x1F\x8B") && is_gzipped_tar(header) {
+            Some(Self::TarGz)
+        } else if header.starts_with(b"Rar!\x1A\x07") {
+            Some(Self::Rar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Gzip only compresses a single file, so the first block has to be decompressed to tell a tarball from any other file
+fn is_gzipped_tar(header : &[u8]) -> bool {
+    let mut block = Vec::with_capacity(TAR_BLOCK_SIZE);
+    // The header is usually cut off in the middle of the stream, whatever was decompressed until then is enough
+    let _ = GzDecoder::new(header).take(TAR_BLOCK_SIZE as u64).read_to_end(&mut block);
+    is_tar_header(&block)
+}
+
+fn is_tar_header(block : &[u8]) -> bool {
+    if block.len() < TAR_BLOCK_SIZE || block[0] == 0 { return false; }
+    if &block[257..262] == b"ustar" { return true; }
+    // Old tar versions have no magic, but every header contains a checksum over itself, with spaces in place of the checksum
+    let stored_checksum = std::str::from_utf8(&block[148..156]).ok()
+        .and_then(|c| u32::from_str_radix(c.trim_matches(|c| c == ' ' || c == '\0'), 8).ok());
+    let checksum : u32 = block[..TAR_BLOCK_SIZE].iter().enumerate()
+        .map(|(i, b)| if (148..156).contains(&i) { b' ' as u32 } else { *b as u32 })
+        .sum();
+    stored_checksum == Some(checksum)
+}
+
+/// Rar only has the attributes to tell links apart: unix modes for archives made on unix, Windows attributes otherwise.
+/// Hard links and file copies can't be told apart from files, but they are extracted into memory and written as plain files.
+fn is_rar_link(file_attr : u32) -> bool {
+    match file_attr & S_IFMT {
+        S_IFREG | S_IFDIR => false,
+        // Windows attributes don't use these bits, except for rarely used ones, so it is safer to refuse those too
+        0 => file_attr & FILE_ATTRIBUTE_REPARSE_POINT != 0,
+        _ => true
+    }
+}
+
+/// Checked entry of an archive, that is safe to write below the output folder
+struct ArchiveEntry {
+    relative_path : PathBuf,
+    is_dir : bool,
+    /// Set for entries outside of the game root, which are not extracted
+    skip : bool
+}
+
+impl ArchiveEntry {
+    fn new(name : &str, is_dir : bool, is_symlink : bool) -> Result<Self, String> {
+        if is_symlink {
+            return Err(format!("Bad archive entry: \"{}\" is a symlink", name));
+        }
+        // Entries like "./" refer to the output folder itself
+        if is_dir && name.replace('\\', "/").split('/').all(|p| p.is_empty() || p == ".") {
+            return Ok(Self { relative_path: PathBuf::new(), is_dir, skip: false });
+        }
+        let relative_path = check_relative_path(name).map_err(|e| format!("Bad archive entry: {}", e))?;
+        Ok(Self { relative_path, is_dir, skip: false })
+    }
+
+    /// Returns the folder this entry is in, if the entry marks it as the game root
+    fn get_game_root(&self) -> Option<PathBuf> {
+        let components : Vec<String> = self.relative_path.iter().map(|c| c.to_string_lossy().to_lowercase()).collect();
+        for (i, component) in components.iter().enumerate() {
+            let is_last = i + 1 == components.len();
+            let is_root_folder = GAME_ROOT_FOLDERS.contains(&component.as_str()) && (!is_last || self.is_dir);
+            let is_root_file = GAME_ROOT_FILES.contains(&component.as_str()) && is_last && !self.is_dir;
+            if is_root_folder || is_root_file {
+                return Some(self.relative_path.iter().take(i).collect());
+            }
+        }
+        None
+    }
+}
+
+/// Finds the folder inside the archive, that corresponds to the game folder and makes all entries relative to it.
+/// Entries outside of that folder are skipped.
+/// Many mods wrap everything in a folder like `TheOtherRoles-v4.3/`, which would otherwise end up in the game folder.
+/// `subpath` overrides the detection, for archives where it picks the wrong folder.
+fn apply_layout<'a>(entries : impl Iterator<Item = &'a mut ArchiveEntry>, subpath : Option<&str>) -> Result<(), String> {
+    let mut entries : Vec<&mut ArchiveEntry> = entries.collect();
+    let root = match subpath {
+        Some(subpath) if !subpath.is_empty() => {
+            let root = check_relative_path(subpath).map_err(|e| format!("Invalid archive subpath: {}", e))?;
+            if !entries.iter().any(|e| e.relative_path.starts_with(&root) && e.relative_path != root) {
+                return Err(format!("The archive does not contain anything in {}", root.display()));
+            }
+            root
+        },
+        // Use the outermost root, in case a mod ships its own BepInEx folder somewhere deeper down
+        _ => match entries.iter().filter_map(|e| e.get_game_root()).min_by_key(|r| r.components().count()) {
+            Some(root) => root,
+            None => return Ok(())
+        }
+    };
+    if root.as_os_str().is_empty() { return Ok(()); }
+    log_info!("Using {} as the game root of the archive", root.display());
+    for entry in entries.iter_mut() {
+        match entry.relative_path.strip_prefix(&root) {
+            Ok(stripped) => entry.relative_path = stripped.to_path_buf(),
+            Err(_) => entry.skip = true
+        }
+    }
+    Ok(())
+}
+
+/// Extracts an archive into `output_path`, checking every entry before anything is written.
+/// Supports zip, 7z, tar.gz and rar archives.
+/// Only the part of the archive, that belongs into the game folder is extracted (see [`apply_layout`]).
+/// Returns the extracted files, relative to `output_path`.
+pub fn extract(archive_path : &Path, output_path : &Path, subpath : Option<&str>, window : &Window) -> Result<Vec<PathBuf>, String> {
+    let Some(format) = ArchiveFormat::detect(archive_path) else {
+        return Err(format!("{} is not a supported archive", archive_path.display()));
+    };
+    window.emit("progress", format!("Extracting")).unwrap();
+    let on_progress = |done : usize, total : usize| {
+        window.emit("progress", format!("Extracting {:.1}%", (done as f64 / total.max(1) as f64) * 100.0)).unwrap();
+    };
+    extract_format(format, archive_path, output_path, subpath, &on_progress)
+}
+
+fn extract_format(format : ArchiveFormat, archive_path : &Path, output_path : &Path, subpath : Option<&str>, on_progress : &dyn Fn(usize, usize)) -> Result<Vec<PathBuf>, String> {
+    match format {
+        ArchiveFormat::Zip => extract_zip(archive_path, output_path, subpath, on_progress),
+        ArchiveFormat::SevenZip => extract_7z(archive_path, output_path, subpath, on_progress),
+        ArchiveFormat::TarGz => extract_tar_gz(archive_path, output_path, subpath, on_progress),
+        ArchiveFormat::Rar => extract_rar(archive_path, output_path, subpath, on_progress)
+    }
+}
+
+fn get_extracted_files<'a>(entries : impl Iterator<Item = &'a ArchiveEntry>) -> Vec<PathBuf> {
+    entries.filter(|e| !e.skip && !e.is_dir).map(|e| e.relative_path.clone()).collect()
+}
+
+fn write_entry(output_path : &Path, entry : &ArchiveEntry, reader : &mut dyn Read) -> Result<(), String> {
+    if entry.skip {
+        // Still consume the data, because some formats depend on reading entries in order
+        return std::io::copy(reader, &mut std::io::sink()).map(|_| ()).or(Err(format!("Cannot read archive")));
+    }
+    let relative_path = &entry.relative_path;
+    let entry_output_path = output_path.join(relative_path);
+    if entry.is_dir {
+        return std::fs::create_dir_all(&entry_output_path).or(Err(format!("Cannot create folder {}", relative_path.display())));
+    }
+    if let Some(parent_dir) = entry_output_path.parent() {
+        std::fs::create_dir_all(parent_dir).or(Err(format!("Cannot create folder {}", relative_path.display())))?;
+    }
+    let mut output_file = std::fs::File::create(&entry_output_path).or(Err(format!("Cannot create {}", relative_path.display())))?;
+    std::io::copy(reader, &mut output_file).or(Err(format!("Cannot extract {}", relative_path.display())))?;
+    output_file.flush().or(Err(format!("Cannot extract {}", relative_path.display())))
+}
+
+fn extract_zip(archive_path : &Path, output_path : &Path, subpath : Option<&str>, on_progress : &dyn Fn(usize, usize)) -> Result<Vec<PathBuf>, String> {
+    let zip_file = std::fs::File::open(archive_path).or(Err(format!("Cannot open zip file")))?;
+    let mut zip_reader = zip::read::ZipArchive::new(zip_file).or(Err(format!("Cannot read zip file")))?;
+    // Validate all entries first, so that a bad archive does not leave a partially extracted mod behind
+    let mut entries = Vec::with_capacity(zip_reader.len());
+    for i in 0..zip_reader.len() {
+        let file = zip_reader.by_index_raw(i).or(Err(format!("Cannot read zip file")))?;
+        let is_symlink = file.unix_mode().map(|m| m & S_IFMT == S_IFLNK).unwrap_or(false);
+        entries.push(ArchiveEntry::new(file.name(), file.is_dir(), is_symlink)?);
+    }
+    apply_layout(entries.iter_mut(), subpath)?;
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.skip { continue; }
+        let mut file = zip_reader.by_index(i).or(Err(format!("Cannot read zip file")))?;
+        write_entry(output_path, entry, &mut file)?;
+        on_progress(i + 1, entries.len());
+    }
+    Ok(get_extracted_files(entries.iter()))
+}
+
+fn extract_7z(archive_path : &Path, output_path : &Path, subpath : Option<&str>, on_progress : &dyn Fn(usize, usize)) -> Result<Vec<PathBuf>, String> {
+    let mut reader = sevenz_rust::SevenZReader::open(archive_path, sevenz_rust::Password::empty())
+        .map_err(|e| format!("Cannot read 7z file: {}", e))?;
+    let mut entries = HashMap::new();
+    for file in &reader.archive().files {
+        // Either a reparse point on windows, or a unix symlink stored in the high bits of the attributes
+        let is_symlink = file.has_windows_attributes && (
+            file.windows_attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0 ||
+            (file.windows_attributes & FILE_ATTRIBUTE_UNIX_EXTENSION != 0 && (file.windows_attributes >> 16) & S_IFMT == S_IFLNK)
+        );
+        entries.insert(file.name().to_string(), ArchiveEntry::new(file.name(), file.is_directory(), is_symlink)?);
+    }
+    apply_layout(entries.values_mut(), subpath)?;
+    let total = entries.len();
+    let mut done = 0;
+    let mut write_result = Ok(());
+    reader.for_each_entries(|file, file_reader| {
+        // Deletion markers of update archives do not contain anything
+        if file.is_anti_item() { return Ok(true); }
+        let entry = &entries[file.name()];
+        write_result = write_entry(output_path, entry, file_reader);
+        done += 1;
+        on_progress(done, total);
+        Ok(write_result.is_ok())
+    }).map_err(|e| format!("Cannot extract 7z file: {}", e))?;
+    write_result?;
+    Ok(get_extracted_files(entries.values()))
+}
+
+fn open_tar_gz(archive_path : &Path) -> Result<tar::Archive<GzDecoder<std::fs::File>>, String> {
+    let file = std::fs::File::open(archive_path).or(Err(format!("Cannot open tar.gz file")))?;
+    Ok(tar::Archive::new(GzDecoder::new(file)))
+}
+
+fn extract_tar_gz(archive_path : &Path, output_path : &Path, subpath : Option<&str>, on_progress : &dyn Fn(usize, usize)) -> Result<Vec<PathBuf>, String> {
+    // Tar archives can only be read sequentially, so the first pass only validates the entries
+    let mut entries = Vec::new();
+    let mut archive = open_tar_gz(archive_path)?;
+    for file in archive.entries().or(Err(format!("Cannot read tar.gz file")))? {
+        let file = file.or(Err(format!("Cannot read tar.gz file")))?;
+        let name = String::from_utf8_lossy(&file.path_bytes()).to_string();
+        let entry_type = file.header().entry_type();
+        let is_symlink = entry_type.is_symlink() || entry_type.is_hard_link();
+        if !is_symlink && !entry_type.is_file() && !entry_type.is_dir() { continue; }
+        entries.push(ArchiveEntry::new(&name, entry_type.is_dir(), is_symlink)?);
+    }
+    apply_layout(entries.iter_mut(), subpath)?;
+    let mut archive = open_tar_gz(archive_path)?;
+    let mut entries_iter = entries.iter();
+    let mut done = 0;
+    for file in archive.entries().or(Err(format!("Cannot read tar.gz file")))? {
+        let mut file = file.or(Err(format!("Cannot read tar.gz file")))?;
+        let entry_type = file.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() { continue; }
+        let Some(entry) = entries_iter.next() else {
+            return Err(format!("tar.gz file changed while extracting"));
+        };
+        write_entry(output_path, entry, &mut file)?;
+        done += 1;
+        on_progress(done, entries.len());
+    }
+    Ok(get_extracted_files(entries.iter()))
+}
+
+fn extract_rar(archive_path : &Path, output_path : &Path, subpath : Option<&str>, on_progress : &dyn Fn(usize, usize)) -> Result<Vec<PathBuf>, String> {
+    let mut entries = HashMap::new();
+    let listing = unrar::Archive::new(archive_path).open_for_listing().map_err(|e| format!("Cannot read rar file: {}", e))?;
+    for header in listing {
+        let header = header.map_err(|e| format!("Cannot read rar file: {}", e))?;
+        let name = header.filename.to_string_lossy().to_string();
+        let is_symlink = is_rar_link(header.file_attr);
+        entries.insert(header.filename.clone(), ArchiveEntry::new(&name, header.is_directory(), is_symlink)?);
+    }
+    apply_layout(entries.values_mut(), subpath)?;
+    let total = entries.len();
+    let mut done = 0;
+    let mut archive = unrar::Archive::new(archive_path).open_for_processing().map_err(|e| format!("Cannot read rar file: {}", e))?;
+    while let Some(header) = archive.read_header().map_err(|e| format!("Cannot read rar file: {}", e))? {
+        let Some(entry) = entries.get(&header.entry().filename) else {
+            return Err(format!("rar file changed while extracting"));
+        };
+        archive = if entry.is_dir || entry.skip {
+            write_entry(output_path, entry, &mut std::io::empty())?;
+            header.skip().map_err(|e| format!("Cannot read rar file: {}", e))?
+        } else {
+            let (data, rest) = header.read().map_err(|e| format!("Cannot extract {}: {}", entry.relative_path.display(), e))?;
+            write_entry(output_path, entry, &mut data.as_slice())?;
+            rest
+        };
+        done += 1;
+        on_progress(done, total);
+    }
+    Ok(get_extracted_files(entries.values()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every fixture exists in each of these formats, see `test_data/make_archives.py`
+    const FIXTURE_FORMATS : [(&str, ArchiveFormat); 4] = [
+        ("zip", ArchiveFormat::Zip),
+        ("7z", ArchiveFormat::SevenZip),
+        ("tar.gz", ArchiveFormat::TarGz),
+        ("rar", ArchiveFormat::Rar)
+    ];
+
+    fn get_fixture_path(file_name : &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data").join("archives").join(file_name)
+    }
+
+    /// Extracts a fixture into an empty folder inside a temporary folder, so anything written next to it shows up as well
+    fn extract_fixture(file_name : &str) -> (PathBuf, Result<Vec<PathBuf>, String>) {
+        let temporary_path = std::env::temp_dir().join(format!("sussy_archive_test_{}_{}", file_name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&temporary_path);
+        let output_path = temporary_path.join("output");
+        std::fs::create_dir_all(&output_path).unwrap();
+        let archive_path = get_fixture_path(file_name);
+        let format = ArchiveFormat::detect(&archive_path).unwrap();
+        let result = extract_format(format, &archive_path, &output_path, None, &|_, _| {});
+        (temporary_path, result)
+    }
+
+    fn list_extracted_files(temporary_path : &Path) -> Vec<String> {
+        let mut files : Vec<String> = walkdir::WalkDir::new(temporary_path).into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| !e.file_type().is_dir())
+            .map(|e| pathdiff::diff_paths(e.path(), temporary_path).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn detects_formats_by_content() {
+        for (extension, format) in FIXTURE_FORMATS {
+            assert_eq!(ArchiveFormat::detect(&get_fixture_path(&format!("mod.{}", extension))), Some(format), "mod.{}", extension);
+        }
+    }
+
+    #[test]
+    fn does_not_take_any_gzip_file_for_a_tarball() {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&[b'x'; 2048]).unwrap();
+        assert_eq!(ArchiveFormat::from_magic_bytes(&encoder.finish().unwrap()), None);
+        let tarball = std::fs::read(get_fixture_path("mod.tar.gz")).unwrap();
+        assert_eq!(ArchiveFormat::from_magic_bytes(&tarball), Some(ArchiveFormat::TarGz));
+    }
+
+    #[test]
+    fn extracts_the_game_root_of_every_format() {
+        for (extension, _) in FIXTURE_FORMATS {
+            let (temporary_path, result) = extract_fixture(&format!("mod.{}", extension));
+            let files = list_extracted_files(&temporary_path);
+            let plugin = std::fs::read(temporary_path.join("output/BepInEx/plugins/x.dll"));
+            std::fs::remove_dir_all(&temporary_path).unwrap();
+            let mut extracted : Vec<String> = result.unwrap().iter().map(|p| format!("output/{}", p.to_string_lossy().replace('\\', "/"))).collect();
+            extracted.sort();
+            assert_eq!(files, vec!["output/BepInEx/config/x.cfg", "output/BepInEx/plugins/x.dll"], "mod.{}", extension);
+            // Folders are not part of the returned files
+            assert_eq!(extracted, files, "mod.{}", extension);
+            assert_eq!(plugin.unwrap(), b"not really a dll\n", "mod.{}", extension);
+        }
+    }
+
+    #[test]
+    fn rejects_symlinks_in_every_format() {
+        for (extension, _) in FIXTURE_FORMATS {
+            let (temporary_path, result) = extract_fixture(&format!("symlink.{}", extension));
+            let files = list_extracted_files(&temporary_path);
+            std::fs::remove_dir_all(&temporary_path).unwrap();
+            assert!(result.unwrap_err().contains("is a symlink"), "symlink.{}", extension);
+            assert!(files.is_empty(), "symlink.{} wrote {:?}", extension, files);
+        }
+    }
+
+    #[test]
+    fn detects_rar_links_from_unix_modes_and_windows_attributes() {
+        assert!(!is_rar_link(S_IFREG | 0o644));
+        assert!(!is_rar_link(S_IFDIR | 0o2775));
+        assert!(is_rar_link(S_IFLNK | 0o777));
+        // Named pipes and devices are not files either
+        assert!(is_rar_link(0o010644));
+        assert!(!is_rar_link(0x20));
+        assert!(!is_rar_link(0x10));
+        assert!(is_rar_link(0x20 | FILE_ATTRIBUTE_REPARSE_POINT));
+    }
+
+    #[test]
+    fn rejects_paths_leaving_the_archive_in_every_format() {
+        for (extension, _) in FIXTURE_FORMATS {
+            let (temporary_path, result) = extract_fixture(&format!("traversal.{}", extension));
+            let files = list_extracted_files(&temporary_path);
+            std::fs::remove_dir_all(&temporary_path).unwrap();
+            assert!(result.unwrap_err().contains("points outside of its folder"), "traversal.{}", extension);
+            assert!(files.is_empty(), "traversal.{} wrote {:?}", extension, files);
+        }
+    }
+
+    #[test]
+    fn accepts_plain_relative_paths() {
+        assert_eq!(check_relative_path("BepInEx/plugins/Mod.dll").unwrap(), Path::new("BepInEx").join("plugins").join("Mod.dll"));
+        assert_eq!(check_relative_path("BepInEx\\config\\mod.cfg").unwrap(), Path::new("BepInEx").join("config").join("mod.cfg"));
+        assert_eq!(check_relative_path("./BepInEx//plugins/").unwrap(), Path::new("BepInEx").join("plugins"));
+    }
+
+    #[test]
+    fn rejects_paths_leaving_the_folder() {
+        for path in ["../evil.dll", "BepInEx/../../evil.dll", "..\\evil.dll", "BepInEx\\..\\..\\evil.dll", ".."] {
+            assert!(check_relative_path(path).is_err(), "{} was accepted", path);
+        }
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        for path in ["/etc/passwd", "\\Windows\\System32\\evil.dll", "//server/share/evil.dll", "\\\\server\\share\\evil.dll"] {
+            assert!(check_relative_path(path).is_err(), "{} was accepted", path);
+        }
+    }
+
+    #[test]
+    fn rejects_drive_prefixes() {
+        for path in ["C:/Windows/evil.dll", "C:\\Windows\\evil.dll", "C:evil.dll", "c:"] {
+            assert!(check_relative_path(path).is_err(), "{} was accepted", path);
+        }
+    }
+
+    #[test]
+    fn rejects_empty_and_reserved_names() {
+        for path in ["", ".", "./", "BepInEx/CON", "BepInEx/nul.txt", "BepInEx/evil?.dll"] {
+            assert!(check_relative_path(path).is_err(), "{} was accepted", path);
+        }
+    }
+
+    #[test]
+    fn joins_only_inside_the_base() {
+        let base = Path::new("game");
+        assert_eq!(safe_join(base, "BepInEx/plugins/Mod.dll").unwrap(), base.join("BepInEx").join("plugins").join("Mod.dll"));
+        for path in ["../evil.dll", "/evil.dll", "C:\\evil.dll"] {
+            assert!(safe_join(base, path).is_err(), "{} was accepted", path);
+        }
+    }
+
+    /// Lays out entries like the extractors do and returns the paths of those that would be extracted.
+    /// Names ending in a slash are folders.
+    fn lay_out(names : &[&str], subpath : Option<&str>) -> Result<Vec<String>, String> {
+        let mut entries : Vec<ArchiveEntry> = names.iter()
+            .map(|n| ArchiveEntry::new(n, n.ends_with('/'), false).unwrap())
+            .collect();
+        apply_layout(entries.iter_mut(), subpath)?;
+        Ok(entries.iter()
+            .filter(|e| !e.skip && !e.relative_path.as_os_str().is_empty())
+            .map(|e| e.relative_path.to_string_lossy().replace('\\', "/"))
+            .collect())
+    }
+
+    #[test]
+    fn finds_the_game_root_inside_a_wrapping_folder() {
+        let names = ["README.txt", "Mod-v1/", "Mod-v1/changelog.md", "Mod-v1/BepInEx/", "Mod-v1/BepInEx/plugins/x.dll", "Mod-v1/BepInEx/config/x.cfg"];
+        // Files next to the BepInEx folder belong into the game folder, but nothing outside of the wrapping folder does
+        assert_eq!(lay_out(&names, None).unwrap(), vec!["changelog.md", "BepInEx", "BepInEx/plugins/x.dll", "BepInEx/config/x.cfg"]);
+    }
+
+    #[test]
+    fn finds_the_game_root_by_the_files_of_bepinex() {
+        let names = ["Pack/winhttp.dll", "Pack/doorstop_config.ini", "Pack/BepInEx/core/BepInEx.Core.dll", "Source/Plugin.cs"];
+        assert_eq!(lay_out(&names, None).unwrap(), vec!["winhttp.dll", "doorstop_config.ini", "BepInEx/core/BepInEx.Core.dll"]);
+    }
+
+    #[test]
+    fn uses_the_outermost_game_root() {
+        let names = ["BepInEx/plugins/x.dll", "BepInEx/plugins/Bundled/BepInEx/plugins/y.dll"];
+        assert_eq!(lay_out(&names, None).unwrap(), names);
+    }
+
+    #[test]
+    fn keeps_archives_without_a_game_root() {
+        assert_eq!(lay_out(&["x.dll"], None).unwrap(), vec!["x.dll"]);
+        // A file named like the folder is not the folder
+        assert_eq!(lay_out(&["Mod/bepinex", "Mod/x.dll"], None).unwrap(), vec!["Mod/bepinex", "Mod/x.dll"]);
+    }
+
+    #[test]
+    fn uses_an_explicit_subpath() {
+        let names = ["Mod-v1/BepInEx/plugins/x.dll", "Mod-v1/Extras/BepInEx/plugins/extra.dll"];
+        assert_eq!(lay_out(&names, Some("Mod-v1/Extras")).unwrap(), vec!["BepInEx/plugins/extra.dll"]);
+        assert_eq!(lay_out(&names, Some("Mod-v1\\Extras\\")).unwrap(), vec!["BepInEx/plugins/extra.dll"]);
+        // An empty subpath falls back to the detection
+        assert_eq!(lay_out(&names, Some("")).unwrap(), vec!["BepInEx/plugins/x.dll", "Extras/BepInEx/plugins/extra.dll"]);
+    }
+
+    #[test]
+    fn rejects_subpaths_that_are_not_in_the_archive() {
+        let names = ["Mod-v1/BepInEx/plugins/x.dll"];
+        assert_eq!(lay_out(&names, Some("Mod-v2")), Err("The archive does not contain anything in Mod-v2".to_string()));
+        // The subpath has to be a folder with something in it
+        assert!(lay_out(&names, Some("Mod-v1/BepInEx/plugins/x.dll")).is_err());
+        assert!(lay_out(&names, Some("../Mod-v1")).unwrap_err().starts_with("Invalid archive subpath"));
+    }
+}
@@ -0,0 +1,651 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use walkdir::WalkDir;
+use crate::config::{Config, Storefront};
+use crate::mod_manager::ModType;
+use crate::{archive, bepinex, blob_store, steam, sync};
+
+/// Hashes of all files in the backup, written once the copy finished
+const MANIFEST_NAME : &'static str = "sussy_backup_manifest.json";
+/// Written last, so a backup without it was interrupted
+const COMPLETE_MARKER_NAME : &'static str = "sussy_backup_complete";
+/// Folder in the backup folder, that contains the content of all backups
+const STORE_FOLDER_NAME : &'static str = "objects";
+/// Enough of the hash to tell builds with the same version apart
+const BUILD_HASH_LENGTH : usize = 8;
+/// A backup is built in a hidden folder with this suffix and renamed once complete
+const PARTIAL_SUFFIX : &'static str = ".partial";
+/// The backup being replaced is kept under this suffix until the new one is in place
+const OLD_SUFFIX : &'static str = ".old";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub size : u64,
+    pub sha256 : String
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// Unix timestamp in seconds
+    pub created_at : u64,
+    #[serde(default)]
+    pub build : Option<GameBuild>,
+    /// Paths relative to the game folder, always separated by `/`
+    pub files : BTreeMap<String, BackupFile>
+}
+
+/// Identifies a release of the game, so there can be one vanilla backup per release
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameBuild {
+    /// The version and a hash of the game code, like `2023.3.28-1a2b3c4d`. Also the name of the backup folder.
+    pub id : String,
+    /// The version shown in game, read from the game data
+    pub version : Option<String>,
+    /// Only known for Steam installs, Steam changes it with every update
+    pub steam_build_id : Option<String>
+}
+
+/// A vanilla backup of one build of the game
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotInfo {
+    pub build : GameBuild,
+    pub created_at : u64,
+    pub file_count : usize,
+    /// Size of all files, without taking the files shared with other snapshots into account
+    pub size : u64
+}
+
+/// How much space the backups take up
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageUsage {
+    pub snapshot_count : usize,
+    pub blob_count : usize,
+    /// Size of the compressed blobs on disk
+    pub stored_size : u64,
+    /// What the backups would take up as plain copies of the game
+    pub original_size : u64,
+    pub unreferenced_blob_count : usize,
+    pub unreferenced_size : u64
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupStatus {
+    pub exists : bool,
+    pub complete : bool,
+    /// Backups made by older versions have no manifest and can't be verified
+    pub has_manifest : bool,
+    pub created_at : Option<u64>,
+    pub missing : Vec<String>,
+    pub corrupted : Vec<String>
+}
+
+/// How the game folder differs from the backup
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GameChanges {
+    pub added : Vec<String>,
+    pub modified : Vec<String>,
+    pub removed : Vec<String>
+}
+
+fn to_manifest_path(relative_path : &Path) -> String {
+    relative_path.components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_backup_metadata(manifest_path : &str) -> bool {
+    manifest_path == MANIFEST_NAME || manifest_path == COMPLETE_MARKER_NAME
+}
+
+/// Files of a folder by their manifest path
+fn list_files(folder : &Path) -> BTreeMap<String, PathBuf> {
+    WalkDir::new(folder).into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| Some((to_manifest_path(&pathdiff::diff_paths(e.path(), folder)?), e.path().to_path_buf())))
+        .filter(|(p, _)| !is_backup_metadata(p))
+        .collect()
+}
+
+/// The version of Among Us is stored as a length prefixed string like `2023.3.28` in `globalgamemanagers`
+pub fn read_game_version(among_us_path : &Path) -> Option<String> {
+    let data = std::fs::read(among_us_path.join("Among Us_Data/globalgamemanagers")).ok()?;
+    let is_version = |s : &str| {
+        let parts : Vec<&str> = s.split('.').collect();
+        // Hotfixes get a letter appended, like `2022.12.14e`
+        let patch = parts.get(2).map(|p| p.strip_suffix(|c : char| c.is_ascii_lowercase()).unwrap_or(p)).unwrap_or("");
+        let is_number = |n : &str| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit());
+        parts.len() == 3 && parts[0].len() == 4 && is_number(parts[0]) && is_number(parts[1]) && is_number(patch)
+    };
+    (0..data.len().saturating_sub(4)).find_map(|offset| {
+        let length = u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+        if !(8..=12).contains(&length) { return None; }
+        let bytes = data.get(offset + 4..offset + 4 + length)?;
+        let text = std::str::from_utf8(bytes).ok()?;
+        if is_version(text) { Some(text.to_string()) } else { None }
+    })
+}
+
+/// Works for modded games too, because BepInEx doesn't touch the game code
+pub fn detect_build(among_us_path : &Path, storefront : Storefront) -> Option<GameBuild> {
+    // IL2CPP builds have their code in GameAssembly.dll, older ones only have the executable
+    let code_path = ["GameAssembly.dll", "Among Us.exe"].iter().map(|n| among_us_path.join(n)).find(|p| p.is_file())?;
+    let code_hash = sync::hash_file_cached(&code_path).ok()?;
+    let version = read_game_version(among_us_path);
+    let steam_build_id = match storefront {
+        Storefront::Steam => steam::find_among_us().and_then(|i| i.build_id),
+        _ => None
+    };
+    Some(GameBuild {
+        id: format!("{}-{}", version.as_deref().unwrap_or("unknown"), &code_hash[..BUILD_HASH_LENGTH]),
+        version,
+        steam_build_id
+    })
+}
+
+pub fn get_snapshot_path(backup_path : &Path, build_id : &str) -> Result<PathBuf, String> {
+    archive::check_name(build_id).map_err(|e| format!("Invalid build: {}", e))?;
+    Ok(backup_path.join(build_id))
+}
+
+/// The backup of the given build, or of the installed build if none is given
+pub fn resolve_snapshot_path(config : &Config, build_id : Option<&str>) -> Result<PathBuf, String> {
+    let backup_path = Path::new(&config.backup_among_us_path);
+    match build_id {
+        Some(build_id) => get_snapshot_path(backup_path, build_id),
+        None => {
+            let build = detect_build(Path::new(&config.among_us_path), config.storefront).ok_or("Cannot detect the build of Among Us")?;
+            get_snapshot_path(backup_path, &build.id)
+        }
+    }
+}
+
+/// Backups of all builds share one store, which is next to them
+fn get_store_path(snapshot_path : &Path) -> PathBuf {
+    snapshot_path.parent().unwrap_or(Path::new(".")).join(STORE_FOLDER_NAME)
+}
+
+pub fn is_complete(snapshot_path : &Path) -> bool {
+    snapshot_path.join(COMPLETE_MARKER_NAME).is_file() && snapshot_path.join(MANIFEST_NAME).is_file()
+}
+
+pub fn read_manifest(snapshot_path : &Path) -> Result<BackupManifest, String> {
+    let manifest_path = snapshot_path.join(MANIFEST_NAME);
+    let text = std::fs::read_to_string(&manifest_path).or(Err(format!("Cannot read {}", manifest_path.display())))?;
+    serde_json::from_str(&text).map_err(|e| format!("Cannot parse {}: {}", manifest_path.display(), e))
+}
+
+/// All complete backups, oldest first
+pub fn list_snapshots(backup_path : &Path) -> Vec<SnapshotInfo> {
+    let Ok(read_dir) = backup_path.read_dir() else { return vec![] };
+    let mut snapshots : Vec<SnapshotInfo> = read_dir.filter_map(|e| e.ok())
+        // Backups that are being built or replaced are hidden
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .filter(|e| is_complete(&e.path()))
+        .filter_map(|e| {
+            let manifest = read_manifest(&e.path()).ok()?;
+            Some(SnapshotInfo {
+                build: manifest.build.unwrap_or(GameBuild {
+                    id: e.file_name().to_string_lossy().to_string(),
+                    version: None,
+                    steam_build_id: None
+                }),
+                created_at: manifest.created_at,
+                file_count: manifest.files.len(),
+                size: manifest.files.values().map(|f| f.size).sum()
+            })
+        })
+        .collect();
+    snapshots.sort_by_key(|s| s.created_at);
+    snapshots
+}
+
+/// Older versions kept a plain copy of the game directly in the backup folder. Its files are moved into the store,
+/// unless there already is a backup of its build.
+fn migrate_single_backup(backup_path : &Path) -> Result<(), String> {
+    if !backup_path.join("Among Us.exe").is_file() { return Ok(()) }
+    let build = detect_build(backup_path, Storefront::Unknown).ok_or("Cannot detect the build of the backup")?;
+    let snapshot_path = get_snapshot_path(backup_path, &build.id)?;
+    // Only the store and the backups of the current layout are next to the copy
+    let read_dir = backup_path.read_dir().or(Err(format!("Cannot read {}", backup_path.display())))?;
+    let copied_names : HashSet<String> = read_dir.filter_map(|e| e.ok())
+        .filter(|e| e.file_name() != STORE_FOLDER_NAME && !is_complete(&e.path()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|n| !n.starts_with('.'))
+        .collect();
+    if !is_complete(&snapshot_path) {
+        let copied_files = list_files(backup_path).into_iter()
+            .filter(|(manifest_path, _)| copied_names.contains(manifest_path.split('/').next().unwrap_or("")))
+            .collect();
+        write_snapshot(backup_path, &build, copied_files)?;
+        sync::save_hash_cache();
+        log_info!("Moved the backup of Among Us into the store as {}", snapshot_path.display());
+    }
+    // The executable goes last, so an interrupted removal is finished on the next start
+    let mut copied_names : Vec<String> = copied_names.into_iter().collect();
+    copied_names.sort_by_key(|n| n == "Among Us.exe");
+    for name in &copied_names {
+        let path = backup_path.join(name);
+        let result = if path.is_dir() { std::fs::remove_dir_all(&path) } else { std::fs::remove_file(&path) };
+        result.or(Err(format!("Cannot remove {}", path.display())))?;
+    }
+    Ok(())
+}
+
+/// Brings backups made by older versions into the current layout
+pub fn migrate(backup_path : &Path) -> Result<(), String> {
+    migrate_single_backup(backup_path)?;
+    let Ok(read_dir) = backup_path.read_dir() else { return Ok(()) };
+    for entry in read_dir.filter_map(|e| e.ok()) {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        // The launcher was closed while a backup was being replaced, so the old one is put back
+        if let Some(build_id) = file_name.strip_prefix('.').and_then(|n| n.strip_suffix(OLD_SUFFIX)) {
+            let snapshot_path = get_snapshot_path(backup_path, build_id)?;
+            if !is_complete(&snapshot_path) && is_complete(&entry.path()) {
+                if snapshot_path.exists() {
+                    std::fs::remove_dir_all(&snapshot_path).or(Err(format!("Cannot remove {}", snapshot_path.display())))?;
+                }
+                std::fs::rename(entry.path(), &snapshot_path).or(Err(format!("Cannot move {}", entry.path().display())))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Everything needed to back up the game, so the backup can be made without holding on to the config
+pub struct BackupSource {
+    among_us_path : PathBuf,
+    backup_path : PathBuf,
+    storefront : Storefront,
+    /// Files that mods installed into the game folder, which must not end up in a vanilla backup.
+    /// If the mod replaced a vanilla file, this is where it saved the original.
+    installed_files : HashMap<String, Option<PathBuf>>,
+    /// Files of mods installed before their files were recorded, with their copy in the mod folder.
+    /// They are only left out if the game folder has the same file, otherwise it is still the vanilla one.
+    unrecorded_files : HashMap<String, PathBuf>
+}
+
+impl BackupSource {
+    pub fn new(config : &Config) -> Self {
+        let mut installed_files = HashMap::new();
+        let mut unrecorded_files = HashMap::new();
+        for modification in &config.downloaded {
+            let relative_paths = modification.get_installed_files(config);
+            if matches!(modification.mod_type, ModType::Files) && modification.installed_files.is_none() {
+                let Ok(mod_folder) = modification.get_mod_folder(config) else { continue; };
+                unrecorded_files.extend(relative_paths.iter().map(|p| (to_manifest_path(p), mod_folder.join(p))));
+                continue;
+            }
+            let originals_folder = modification.get_originals_folder().ok();
+            for relative_path in relative_paths {
+                let original = originals_folder.as_ref().map(|f| f.join(&relative_path)).filter(|p| p.is_file());
+                installed_files.insert(to_manifest_path(&relative_path), original);
+            }
+        }
+        Self {
+            among_us_path: PathBuf::from(&config.among_us_path),
+            backup_path: PathBuf::from(&config.backup_among_us_path),
+            storefront: config.storefront,
+            installed_files,
+            unrecorded_files
+        }
+    }
+
+    /// Where the vanilla version of a file in the game folder is, or `None` if the vanilla game doesn't have it
+    fn get_vanilla_path(&self, manifest_path : &str, path : &Path, previous : Option<&BackupManifest>) -> Option<PathBuf> {
+        // Whatever is still the same as in the last backup of this build is vanilla, no matter who else ships it
+        if previous.and_then(|m| m.files.get(manifest_path)).map(|f| is_unchanged(path, f)).unwrap_or(false) {
+            return Some(path.to_path_buf());
+        }
+        let first_component = manifest_path.split('/').next().unwrap_or("");
+        if bepinex::BEPINEX_FILES.contains(&first_component) { return None; }
+        if let Some(original) = self.installed_files.get(manifest_path) {
+            return original.clone();
+        }
+        match self.unrecorded_files.get(manifest_path) {
+            Some(mod_path) if is_same_file(path, mod_path) => None,
+            _ => Some(path.to_path_buf())
+        }
+    }
+}
+
+fn is_same_file(path : &Path, other_path : &Path) -> bool {
+    let get_size = |p : &Path| p.metadata().map(|m| m.len()).ok();
+    get_size(path).is_some() && get_size(path) == get_size(other_path) &&
+        sync::hash_file_cached(path).ok() == sync::hash_file_cached(other_path).ok()
+}
+
+/// Backs up the vanilla files of the installed build, replacing an existing backup of the same build.
+/// The files are put into the store, so files that didn't change between builds are only stored once.
+/// The backup is built next to the existing one and only replaces it once it is complete.
+pub fn create(source : &BackupSource) -> Result<SnapshotInfo, String> {
+    let among_us_path = source.among_us_path.as_path();
+    let backup_path = source.backup_path.as_path();
+    if !among_us_path.join("Among Us.exe").is_file() {
+        return Err(format!("Among Us.exe is missing in {}", among_us_path.display()));
+    }
+    let build = detect_build(among_us_path, source.storefront).ok_or("Cannot detect the build of Among Us")?;
+    let snapshot_path = get_snapshot_path(backup_path, &build.id)?;
+    let previous = if is_complete(&snapshot_path) { read_manifest(&snapshot_path).ok() } else { None };
+    let vanilla_files = list_files(among_us_path).into_iter()
+        .filter_map(|(manifest_path, path)| Some((source.get_vanilla_path(&manifest_path, &path, previous.as_ref())?, manifest_path)))
+        .map(|(vanilla_path, manifest_path)| (manifest_path, vanilla_path))
+        .collect();
+    let manifest = write_snapshot(backup_path, &build, vanilla_files)?;
+    sync::save_hash_cache();
+    log_info!("Backed up {} files of Among Us {} to {}", manifest.files.len(), build.id, snapshot_path.display());
+    Ok(SnapshotInfo {
+        build,
+        created_at: manifest.created_at,
+        file_count: manifest.files.len(),
+        size: manifest.files.values().map(|f| f.size).sum()
+    })
+}
+
+/// Puts the files into the store and writes the manifest of a new backup, which replaces the backup of the same build once it is complete
+fn write_snapshot(backup_path : &Path, build : &GameBuild, files : BTreeMap<String, PathBuf>) -> Result<BackupManifest, String> {
+    let snapshot_path = get_snapshot_path(backup_path, &build.id)?;
+    let partial_path = backup_path.join(format!(".{}{}", build.id, PARTIAL_SUFFIX));
+    if partial_path.exists() {
+        std::fs::remove_dir_all(&partial_path).or(Err(format!("Cannot remove {}", partial_path.display())))?;
+    }
+    std::fs::create_dir_all(&partial_path).or(Err(format!("Cannot create {}", partial_path.display())))?;
+    let store_path = get_store_path(&partial_path);
+    let mut manifest_files = BTreeMap::new();
+    for (manifest_path, path) in files {
+        let size = path.metadata().map(|m| m.len()).or(Err(format!("Cannot read {}", path.display())))?;
+        let sha256 = blob_store::put(&store_path, &path)?;
+        manifest_files.insert(manifest_path, BackupFile { size, sha256 });
+    }
+    let manifest = BackupManifest {
+        created_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        build: Some(build.clone()),
+        files: manifest_files
+    };
+    let manifest_json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    std::fs::write(partial_path.join(MANIFEST_NAME), manifest_json).or(Err(format!("Cannot write the backup manifest")))?;
+    std::fs::write(partial_path.join(COMPLETE_MARKER_NAME), "").or(Err(format!("Cannot mark the backup as complete")))?;
+    replace_snapshot(&partial_path, &snapshot_path)?;
+    Ok(manifest)
+}
+
+/// Moves a finished backup into place. The old backup is only removed once the new one took its place.
+fn replace_snapshot(partial_path : &Path, snapshot_path : &Path) -> Result<(), String> {
+    let old_path = get_old_snapshot_path(snapshot_path);
+    if snapshot_path.exists() {
+        if old_path.exists() {
+            std::fs::remove_dir_all(&old_path).or(Err(format!("Cannot remove {}", old_path.display())))?;
+        }
+        std::fs::rename(snapshot_path, &old_path).or(Err(format!("Cannot move {}", snapshot_path.display())))?;
+    }
+    if std::fs::rename(partial_path, snapshot_path).is_err() {
+        if old_path.exists() {
+            let _ = std::fs::rename(&old_path, snapshot_path);
+        }
+        return Err(format!("Cannot move the backup to {}", snapshot_path.display()));
+    }
+    if old_path.exists() {
+        std::fs::remove_dir_all(&old_path).or(Err(format!("Cannot remove {}", old_path.display())))?;
+    }
+    Ok(())
+}
+
+fn get_old_snapshot_path(snapshot_path : &Path) -> PathBuf {
+    let name = snapshot_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    snapshot_path.with_file_name(format!(".{}{}", name, OLD_SUFFIX))
+}
+
+/// Only removes the manifest, the space is reclaimed by collecting garbage
+pub fn delete(backup_path : &Path, build_id : &str) -> Result<(), String> {
+    let snapshot_path = get_snapshot_path(backup_path, build_id)?;
+    if !snapshot_path.is_dir() {
+        return Err(format!("There is no backup of {}", build_id));
+    }
+    std::fs::remove_dir_all(&snapshot_path).or(Err(format!("Cannot remove {}", snapshot_path.display())))
+}
+
+/// Checks every file of the backup against its manifest
+pub fn verify(snapshot_path : &Path) -> BackupStatus {
+    let mut status = BackupStatus {
+        exists: snapshot_path.is_dir(),
+        complete: snapshot_path.join(COMPLETE_MARKER_NAME).is_file(),
+        has_manifest: false,
+        created_at: None,
+        missing: vec![],
+        corrupted: vec![]
+    };
+    let Ok(manifest) = read_manifest(snapshot_path) else { return status; };
+    status.has_manifest = true;
+    status.created_at = Some(manifest.created_at);
+    let store_path = get_store_path(snapshot_path);
+    for (manifest_path, file) in &manifest.files {
+        if !blob_store::contains(&store_path, &file.sha256) {
+            status.missing.push(manifest_path.clone());
+        } else if !blob_store::verify(&store_path, &file.sha256) {
+            status.corrupted.push(manifest_path.clone());
+        }
+    }
+    status
+}
+
+fn is_unchanged(path : &Path, file : &BackupFile) -> bool {
+    // Comparing the size first avoids hashing most changed files
+    path.metadata().map(|m| m.len() == file.size).unwrap_or(false) &&
+        sync::hash_file_cached(path).map(|h| h == file.sha256).unwrap_or(false)
+}
+
+/// The manifest of the backup of the installed build, if there is a complete one
+pub fn read_installed_manifest(config : &Config) -> Option<BackupManifest> {
+    let snapshot_path = resolve_snapshot_path(config, None).ok()?;
+    if !is_complete(&snapshot_path) { return None; }
+    read_manifest(&snapshot_path).ok()
+}
+
+/// Whether a file in the game folder is still the one of the vanilla game
+pub fn is_vanilla_file(manifest : &BackupManifest, relative_path : &Path, path : &Path) -> bool {
+    manifest.files.get(&to_manifest_path(relative_path)).map(|f| is_unchanged(path, f)).unwrap_or(false)
+}
+
+pub fn get_changes(among_us_path : &Path, snapshot_path : &Path) -> Result<GameChanges, String> {
+    let manifest = read_manifest(snapshot_path)?;
+    let game_files = list_files(among_us_path);
+    let mut changes = GameChanges::default();
+    for (manifest_path, path) in &game_files {
+        match manifest.files.get(manifest_path) {
+            Some(file) if !is_unchanged(path, file) => changes.modified.push(manifest_path.clone()),
+            Some(_) => {},
+            None => changes.added.push(manifest_path.clone())
+        }
+    }
+    changes.removed = manifest.files.keys().filter(|p| !game_files.contains_key(*p)).cloned().collect();
+    sync::save_hash_cache();
+    Ok(changes)
+}
+
+/// Writes one file from the backup into the game. The store makes sure its content is intact.
+fn restore_file(among_us_path : &Path, snapshot_path : &Path, manifest_path : &str, file : &BackupFile) -> Result<(), String> {
+    let output_path = archive::safe_join(among_us_path, manifest_path)?;
+    if let Some(parent_dir) = output_path.parent() {
+        std::fs::create_dir_all(parent_dir).or(Err(format!("Cannot create {}", parent_dir.display())))?;
+    }
+    blob_store::read_to(&get_store_path(snapshot_path), &file.sha256, &output_path)
+        .map_err(|e| format!("Cannot restore {}: {}", manifest_path, e))
+}
+
+fn remove_file(among_us_path : &Path, manifest_path : &str) -> Result<(), String> {
+    let path = archive::safe_join(among_us_path, manifest_path)?;
+    if path.is_file() {
+        std::fs::remove_file(&path).or(Err(format!("Cannot remove {}", path.display())))?;
+    }
+    Ok(())
+}
+
+/// Removes folders that are left empty and are not part of the vanilla game
+fn remove_empty_folders(among_us_path : &Path, manifest : &BackupManifest) {
+    let vanilla_folders : HashSet<&str> = manifest.files.keys()
+        .flat_map(|p| p.match_indices('/').map(move |(i, _)| &p[..i]))
+        .collect();
+    for entry in WalkDir::new(among_us_path).contents_first(true).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_dir() { continue; }
+        let Some(relative_path) = pathdiff::diff_paths(entry.path(), among_us_path) else { continue; };
+        if relative_path.as_os_str().is_empty() || vanilla_folders.contains(to_manifest_path(&relative_path).as_str()) { continue; }
+        // Fails for folders that still contain something, which is fine
+        let _ = std::fs::remove_dir(entry.path());
+    }
+}
+
+/// Returns the game folder to exactly the state of the backup.
+/// If only some paths are given, only those are restored from the backup or removed, if the vanilla game doesn't have them.
+pub fn restore(among_us_path : &Path, snapshot_path : &Path, only_paths : Option<Vec<String>>) -> Result<(), String> {
+    // Backups of older versions have no marker either, but without hashes it's impossible to tell if they are intact
+    if !is_complete(snapshot_path) {
+        return Err("The backup is incomplete, create it again".to_string());
+    }
+    let manifest = read_manifest(snapshot_path)?;
+    let changes = get_changes(among_us_path, snapshot_path)?;
+    let is_selected = |p : &String| only_paths.as_ref().map(|o| o.contains(p)).unwrap_or(true);
+    for manifest_path in changes.added.iter().filter(|p| is_selected(p)) {
+        remove_file(among_us_path, manifest_path)?;
+    }
+    for manifest_path in changes.modified.iter().chain(changes.removed.iter()).filter(|p| is_selected(p)) {
+        restore_file(among_us_path, snapshot_path, manifest_path, &manifest.files[manifest_path])?;
+    }
+    if only_paths.is_none() {
+        remove_empty_folders(among_us_path, &manifest);
+    }
+    Ok(())
+}
+
+fn get_referenced_blobs(backup_path : &Path) -> HashSet<String> {
+    list_snapshots(backup_path).iter()
+        .filter_map(|s| get_snapshot_path(backup_path, &s.build.id).ok())
+        .filter_map(|p| read_manifest(&p).ok())
+        .flat_map(|m| m.files.into_values().map(|f| f.sha256))
+        .collect()
+}
+
+pub fn get_storage_usage(backup_path : &Path) -> StorageUsage {
+    let snapshots = list_snapshots(backup_path);
+    let referenced_blobs = get_referenced_blobs(backup_path);
+    let blobs = blob_store::list(&backup_path.join(STORE_FOLDER_NAME));
+    let unreferenced : Vec<&(String, u64)> = blobs.iter().filter(|(h, _)| !referenced_blobs.contains(h)).collect();
+    StorageUsage {
+        snapshot_count: snapshots.len(),
+        blob_count: blobs.len(),
+        stored_size: blobs.iter().map(|(_, s)| s).sum(),
+        original_size: snapshots.iter().map(|s| s.size).sum(),
+        unreferenced_blob_count: unreferenced.len(),
+        unreferenced_size: unreferenced.iter().map(|(_, s)| s).sum()
+    }
+}
+
+/// Removes blobs that no complete backup refers to, for example after a backup was deleted
+pub fn collect_garbage(backup_path : &Path) -> Result<StorageUsage, String> {
+    let store_path = backup_path.join(STORE_FOLDER_NAME);
+    let referenced_blobs = get_referenced_blobs(backup_path);
+    blob_store::remove_temporary_files(&store_path);
+    let mut removed = 0;
+    for (hash, _) in blob_store::list(&store_path) {
+        if referenced_blobs.contains(&hash) { continue; }
+        blob_store::remove(&store_path, &hash)?;
+        removed += 1;
+    }
+    log_info!("Removed {} unreferenced blobs from {}", removed, store_path.display());
+    Ok(get_storage_usage(backup_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME_FILES : [(&str, &str); 3] = [("Among Us.exe", "executable"), ("GameAssembly.dll", "game code"), ("Among Us_Data/data.bin", "data")];
+
+    fn create_temporary_folder(name : &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sussy_backup_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    /// Writes the game files the way the backup of older versions copied them
+    fn write_plain_backup(backup_path : &Path) {
+        for (name, content) in GAME_FILES {
+            let path = backup_path.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    #[test]
+    fn moves_the_plain_backup_of_older_versions_into_the_store() {
+        let temporary_path = create_temporary_folder("migrate");
+        let backup_path = temporary_path.join("backup");
+        write_plain_backup(&backup_path);
+        migrate(&backup_path).unwrap();
+        let snapshots = list_snapshots(&backup_path);
+        let snapshot_path = get_snapshot_path(&backup_path, &snapshots[0].build.id).unwrap();
+        let status = verify(&snapshot_path);
+        let game_path = temporary_path.join("game");
+        std::fs::create_dir_all(&game_path).unwrap();
+        restore(&game_path, &snapshot_path, None).unwrap();
+        let restored : Vec<String> = GAME_FILES.iter().map(|(n, _)| std::fs::read_to_string(game_path.join(n)).unwrap()).collect();
+        let leftovers : Vec<String> = GAME_FILES.iter().map(|(n, _)| n.to_string()).filter(|n| backup_path.join(n).exists()).collect();
+        let storage_usage = get_storage_usage(&backup_path);
+        std::fs::remove_dir_all(&temporary_path).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].file_count, GAME_FILES.len());
+        assert!(status.complete && status.missing.is_empty() && status.corrupted.is_empty());
+        assert_eq!(restored, GAME_FILES.iter().map(|(_, c)| c.to_string()).collect::<Vec<_>>());
+        assert!(leftovers.is_empty(), "{:?} was left in the backup folder", leftovers);
+        assert_eq!((storage_usage.blob_count, storage_usage.unreferenced_blob_count), (GAME_FILES.len(), 0));
+    }
+
+    #[test]
+    fn removes_the_plain_backup_if_its_build_already_has_a_backup() {
+        let backup_path = create_temporary_folder("migrate_again");
+        write_plain_backup(&backup_path);
+        migrate(&backup_path).unwrap();
+        let created_at = list_snapshots(&backup_path)[0].created_at;
+        write_plain_backup(&backup_path);
+        std::fs::write(backup_path.join("GameAssembly.dll.bak"), "left over").unwrap();
+        migrate(&backup_path).unwrap();
+        let snapshots = list_snapshots(&backup_path);
+        let is_copy_left = backup_path.join("Among Us.exe").exists() || backup_path.join("GameAssembly.dll.bak").exists();
+        std::fs::remove_dir_all(&backup_path).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].created_at, created_at);
+        assert!(!is_copy_left);
+    }
+
+    fn write_build(backup_path : &Path, id : &str, files : &[(&str, &str)]) {
+        let source_path = backup_path.join(format!("source-{}", id));
+        let mut source_files = BTreeMap::new();
+        for (name, content) in files {
+            std::fs::create_dir_all(&source_path).unwrap();
+            std::fs::write(source_path.join(name), content).unwrap();
+            source_files.insert(name.to_string(), source_path.join(name));
+        }
+        let build = GameBuild { id: id.to_string(), version: None, steam_build_id: None };
+        write_snapshot(backup_path, &build, source_files).unwrap();
+        std::fs::remove_dir_all(&source_path).unwrap();
+    }
+
+    #[test]
+    fn collecting_garbage_keeps_the_blobs_of_remaining_backups() {
+        let backup_path = create_temporary_folder("garbage");
+        write_build(&backup_path, "2023.1.1-aaaaaaaa", &[("Among Us.exe", "shared"), ("GameAssembly.dll", "old code")]);
+        write_build(&backup_path, "2023.2.2-bbbbbbbb", &[("Among Us.exe", "shared"), ("GameAssembly.dll", "new code")]);
+        let usage_before = get_storage_usage(&backup_path);
+        delete(&backup_path, "2023.1.1-aaaaaaaa").unwrap();
+        let usage_after = collect_garbage(&backup_path).unwrap();
+        let snapshot_path = get_snapshot_path(&backup_path, "2023.2.2-bbbbbbbb").unwrap();
+        let status = verify(&snapshot_path);
+        std::fs::remove_dir_all(&backup_path).unwrap();
+        assert_eq!((usage_before.snapshot_count, usage_before.blob_count, usage_before.unreferenced_blob_count), (2, 3, 0));
+        assert_eq!((usage_after.snapshot_count, usage_after.blob_count, usage_after.unreferenced_blob_count), (1, 2, 0));
+        assert!(status.complete && status.missing.is_empty() && status.corrupted.is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use reqwest::Client;
+use serde::{Serialize, Deserialize};
+use tauri::Window;
+use crate::{archive, github_api, sniff, sync, util};
+use crate::mod_manager::ModType;
+use crate::version_info::VersionInfo;
+
+const BEPIS_BUILDS_URL : &'static str = "https://builds.bepinex.dev";
+const BEPIS_BUILDS_PROJECT : &'static str = "bepinex_be";
+/// Among Us is a 32 bit IL2CPP game
+const BEPIS_BUILDS_ARTIFACT_PREFIX : &'static str = "BepInEx-Unity.IL2CPP-win-x86-";
+
+/// Everything BepInEx can put into the game folder, none of it is part of the vanilla game
+pub const BEPINEX_FILES : [&'static str; 7] = ["BepInEx", "winhttp.dll", "doorstop_config.ini", ".doorstop_version", "changelog.txt", "dotnet", "mono"];
+/// What BepInEx writes while the game runs, which is removed with it
+const GENERATED_FILES : [&'static str; 6] = [
+    "BepInEx/interop", "BepInEx/unhollowed", "BepInEx/unity-libs", "BepInEx/cache", "BepInEx/LogOutput.log", "BepInEx/config/BepInEx.cfg"
+];
+/// Removed if the installed files were not recorded. Only files that can't belong to anything else are on this list,
+/// generic names like `dotnet` or `changelog.txt` are left behind.
+const UNRECORDED_FILES : [&'static str; 5] = ["winhttp.dll", "doorstop_config.ini", ".doorstop_version", "BepInEx/core", "BepInEx/patchers"];
+/// Folders inside `BepInEx` that belong to a specific version and are replaced on update.
+/// `plugins` and `config` belong to the user and are kept.
+const BEPINEX_VERSIONED_FOLDERS : [&'static str; 5] = ["core", "interop", "unhollowed", "unity-libs", "cache"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum BepInExSource {
+    /// Releases of a GitHub repository
+    Github { username : String, repository_name : String },
+    /// Bleeding edge builds from builds.bepinex.dev
+    BepisBuilds
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BepInExSettings {
+    pub source : BepInExSource,
+    /// Release tag or build number to install. The newest version is used if it is not set.
+    #[serde(default)]
+    pub pinned_version : Option<String>,
+    /// Version that was installed by the launcher, if it knows it
+    #[serde(default)]
+    pub installed_version : Option<String>,
+    /// Files that were extracted into the game folder, relative to it.
+    /// `None` for installs of older versions, which did not record them.
+    #[serde(default)]
+    pub installed_files : Option<Vec<PathBuf>>
+}
+
+impl Default for BepInExSettings {
+    fn default() -> Self {
+        Self {
+            source: BepInExSource::Github { username: "NuclearPowered".to_string(), repository_name: "BepInEx".to_string() },
+            pinned_version: None,
+            installed_version: None,
+            installed_files: None
+        }
+    }
+}
+
+impl BepInExSettings {
+    /// Remembers an install. Files of earlier versions stay recorded, in case the new version doesn't replace them.
+    pub fn record_install(&mut self, install : BepInExInstall) {
+        let mut installed_files = self.installed_files.take().unwrap_or_default();
+        for path in install.files {
+            if !installed_files.contains(&path) {
+                installed_files.push(path);
+            }
+        }
+        self.installed_version = Some(install.version);
+        self.installed_files = Some(installed_files);
+    }
+
+    /// Called after BepInEx was removed from the game folder
+    pub fn forget_install(&mut self) {
+        self.installed_version = None;
+        self.installed_files = Some(vec![]);
+    }
+}
+
+/// A version of BepInEx that was put into the game folder
+pub struct BepInExInstall {
+    pub version : String,
+    /// The extracted files, relative to the game folder
+    pub files : Vec<PathBuf>
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BepInExStatus {
+    pub installed : bool,
+    pub installed_version : Option<String>,
+    /// Version read from the BepInEx core dll
+    pub core_version : Option<String>,
+    /// Missing or broken files
+    pub problems : Vec<String>
+}
+
+struct BepInExRelease {
+    version : String,
+    download_url : String
+}
+
+async fn resolve_github_release(client : &Client, username : &String, repository_name : &String, pinned_version : &Option<String>) -> Result<BepInExRelease, String> {
+    let release = match pinned_version {
+        Some(tag) => github_api::get_release_by_tag(client, username, repository_name, tag).await?,
+        None => github_api::get_newest_release(client, username, repository_name).await?
+    };
+    let version = release.get("tag_name").and_then(|t| t.as_str()).ok_or("The release has no tag")?.to_string();
+    let download_url = github_api::get_assets(&release).into_iter()
+        .find(|a| matches!(sniff::classify_asset(a), Some(ModType::Files)))
+        .and_then(|a| a.get("browser_download_url").and_then(|u| u.as_str()).map(|u| u.to_string()))
+        .ok_or(format!("The release {} has no BepInEx archive", version))?;
+    Ok(BepInExRelease { version, download_url })
+}
+
+/// The build server has no API, so the artifact links are read from the project page
+async fn resolve_bepis_build(client : &Client, pinned_version : &Option<String>) -> Result<BepInExRelease, String> {
+    let page_url = format!("{}/projects/{}", BEPIS_BUILDS_URL, BEPIS_BUILDS_PROJECT);
+    let response = client.get(&page_url).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Request returned status code {}", response.status().as_u16()))
+    }
+    let page = response.text().await.map_err(|e| e.to_string())?;
+    let build_prefix = format!("/projects/{}/", BEPIS_BUILDS_PROJECT);
+    let mut builds = page.split("href=\"").skip(1).filter_map(|s| {
+        let link = s.split('"').next()?;
+        let build_path = link.strip_prefix(&build_prefix)?;
+        let (build_number, artifact) = build_path.split_once('/')?;
+        if !artifact.starts_with(BEPIS_BUILDS_ARTIFACT_PREFIX) { return None; }
+        Some((build_number.parse::<u32>().ok()?, link.to_string()))
+    });
+    let (build_number, link) = match pinned_version {
+        Some(version) => {
+            let pinned_build = version.trim_start_matches("be.");
+            builds.find(|(n, _)| n.to_string() == pinned_build)
+                .ok_or(format!("The build {} does not exist", version))?
+        },
+        None => builds.max_by_key(|(n, _)| *n).ok_or("No builds were found")?
+    };
+    Ok(BepInExRelease { version: format!("be.{}", build_number), download_url: format!("{}{}", BEPIS_BUILDS_URL, link) })
+}
+
+async fn resolve_release(settings : &BepInExSettings) -> Result<BepInExRelease, String> {
+    let client = util::get_reqwest_client();
+    match &settings.source {
+        BepInExSource::Github { username, repository_name } => resolve_github_release(&client, username, repository_name, &settings.pinned_version).await,
+        BepInExSource::BepisBuilds => resolve_bepis_build(&client, &settings.pinned_version).await
+    }
+}
+
+async fn download_and_extract(release : &BepInExRelease, among_us_path : &Path, window : &Window) -> Result<BepInExInstall, String> {
+    let client = util::get_reqwest_client();
+    let archive_file_path = among_us_path.join("BepInEx.archive");
+    util::download_file(&client, &release.download_url, &archive_file_path, window, "BepInEx").await?;
+    log_info!("Extracting : {}", archive_file_path.display());
+    let result = archive::extract(&archive_file_path, among_us_path, None, window);
+    tokio::fs::remove_file(&archive_file_path).await.or(Err(format!("Could not remove archive file")))?;
+    let files = result?;
+    tokio::fs::create_dir_all(among_us_path.join("BepInEx/plugins")).await.or(Err(format!("Cannot create plugins folder")))?;
+    Ok(BepInExInstall { version: release.version.clone(), files })
+}
+
+pub fn is_installed(among_us_path : &Path) -> bool {
+    among_us_path.join("BepInEx/plugins").is_dir() && among_us_path.join("winhttp.dll").is_file()
+}
+
+/// Installs BepInEx and returns the installed version with its files
+pub async fn install(settings : &BepInExSettings, among_us_path : &Path, window : &Window) -> Result<BepInExInstall, String> {
+    let release = resolve_release(settings).await?;
+    download_and_extract(&release, among_us_path, window).await
+}
+
+/// Installs the newest (or pinned) version, if it differs from the installed one. Plugins and their configs are kept.
+/// Returns the new version with its files, if anything was updated.
+pub async fn update(settings : &BepInExSettings, among_us_path : &Path, window : &Window) -> Result<Option<BepInExInstall>, String> {
+    let release = resolve_release(settings).await?;
+    if is_installed(among_us_path) && settings.installed_version.as_ref() == Some(&release.version) {
+        return Ok(None);
+    }
+    let bepinex_path = among_us_path.join("BepInEx");
+    for folder in BEPINEX_VERSIONED_FOLDERS {
+        let path = bepinex_path.join(folder);
+        if path.is_dir() {
+            tokio::fs::remove_dir_all(&path).await.or(Err(format!("Cannot remove {}", path.display())))?;
+        }
+    }
+    Ok(Some(download_and_extract(&release, among_us_path, window).await?))
+}
+
+/// Removes the given files and the folders that are left empty by that
+fn remove_files(among_us_path : &Path, relative_paths : &[PathBuf]) -> Result<(), String> {
+    for relative_path in relative_paths {
+        let path = archive::safe_join(among_us_path, &relative_path.to_string_lossy())?;
+        let result = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else if path.is_file() {
+            std::fs::remove_file(&path)
+        } else {
+            continue;
+        };
+        result.or(Err(format!("Cannot remove {}", path.display())))?;
+    }
+    let mut folders : Vec<&Path> = relative_paths.iter()
+        .flat_map(|p| p.ancestors().skip(1))
+        .chain([Path::new("BepInEx/plugins")])
+        .filter(|p| !p.as_os_str().is_empty())
+        .collect::<BTreeSet<&Path>>().into_iter().collect();
+    // The deepest first, so their parents are empty once they are reached. Folders that still contain something are kept.
+    folders.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+    for folder in folders {
+        let _ = std::fs::remove_dir(among_us_path.join(folder));
+    }
+    Ok(())
+}
+
+/// Removes the files BepInEx was installed with and the ones it generated.
+/// Plugins and configs of mods are kept, they are removed with their mods.
+pub async fn uninstall(settings : &BepInExSettings, among_us_path : &Path) -> Result<(), String> {
+    let mut files : Vec<PathBuf> = match &settings.installed_files {
+        Some(installed_files) => installed_files.clone(),
+        None => {
+            log_info!("The files of BepInEx were not recorded, only the ones that belong to it for sure are removed");
+            UNRECORDED_FILES.iter().map(PathBuf::from).collect()
+        }
+    };
+    files.extend(GENERATED_FILES.iter().map(PathBuf::from));
+    let among_us_path = among_us_path.to_path_buf();
+    sync::run_blocking(move || remove_files(&among_us_path, &files)).await
+}
+
+/// Reads the path of the assembly doorstop loads. The key is `targetAssembly` in older and `target_assembly` in newer versions.
+fn read_doorstop_target(among_us_path : &Path) -> Option<PathBuf> {
+    let doorstop_config = std::fs::read_to_string(among_us_path.join("doorstop_config.ini")).ok()?;
+    doorstop_config.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        let key = key.trim();
+        if key != "targetAssembly" && key != "target_assembly" { return None; }
+        Some(among_us_path.join(value.trim().replace('\\', "/")))
+    })
+}
+
+/// BepInEx 6 calls its core dll `BepInEx.Core.dll`, BepInEx 5 just `BepInEx.dll`
+fn find_core_dll(among_us_path : &Path) -> Option<PathBuf> {
+    let core_path = among_us_path.join("BepInEx/core");
+    ["BepInEx.Core.dll", "BepInEx.dll"].iter().map(|n| core_path.join(n)).find(|p| p.is_file())
+}
+
+pub fn verify(settings : &BepInExSettings, among_us_path : &Path) -> BepInExStatus {
+    let mut problems = vec![];
+    for name in ["winhttp.dll", "doorstop_config.ini"] {
+        if !among_us_path.join(name).is_file() {
+            problems.push(format!("{} is missing", name));
+        }
+    }
+    if !among_us_path.join("BepInEx/plugins").is_dir() {
+        problems.push("The plugins folder is missing".to_string());
+    }
+    let core_dll = find_core_dll(among_us_path);
+    if core_dll.is_none() {
+        problems.push("The BepInEx core dll is missing".to_string());
+    }
+    match read_doorstop_target(among_us_path) {
+        Some(target) if !target.is_file() => problems.push(format!("The doorstop target {} is missing", target.display())),
+        None if among_us_path.join("doorstop_config.ini").is_file() => problems.push("doorstop_config.ini has no target assembly".to_string()),
+        _ => {}
+    }
+    let core_version = core_dll
+        .and_then(|p| VersionInfo::read_file(&p).ok())
+        .and_then(|v| v.strings.get("ProductVersion").cloned().or(v.product_version.map(|p| p.to_string())));
+    BepInExStatus {
+        installed: among_us_path.join("BepInEx").is_dir(),
+        installed_version: settings.installed_version.clone(),
+        core_version,
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_files_of_earlier_installs_recorded() {
+        let mut settings = BepInExSettings::default();
+        settings.record_install(BepInExInstall { version: "v6.0.0-be.1".to_string(), files: vec![PathBuf::from("winhttp.dll"), PathBuf::from("changelog.txt")] });
+        settings.record_install(BepInExInstall { version: "v6.0.0-be.2".to_string(), files: vec![PathBuf::from("winhttp.dll"), PathBuf::from("dotnet/coreclr.dll")] });
+        assert_eq!(settings.installed_version.as_deref(), Some("v6.0.0-be.2"));
+        assert_eq!(settings.installed_files, Some(vec![PathBuf::from("winhttp.dll"), PathBuf::from("changelog.txt"), PathBuf::from("dotnet/coreclr.dll")]));
+    }
+
+    #[test]
+    fn removes_only_the_given_files_and_empty_folders() {
+        let among_us_path = std::env::temp_dir().join(format!("sussy_bepinex_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&among_us_path);
+        for file in ["winhttp.dll", "changelog.txt", "BepInEx/core/BepInEx.Core.dll", "BepInEx/interop/Assembly-CSharp.dll", "BepInEx/plugins/Mod.dll", "dotnet/coreclr.dll"] {
+            std::fs::create_dir_all(among_us_path.join(file).parent().unwrap()).unwrap();
+            std::fs::write(among_us_path.join(file), file).unwrap();
+        }
+        let installed_files = ["winhttp.dll", "BepInEx/core/BepInEx.Core.dll", "dotnet/coreclr.dll", "BepInEx/interop"].map(PathBuf::from);
+        remove_files(&among_us_path, &installed_files).unwrap();
+        let mut remaining : Vec<String> = walkdir::WalkDir::new(&among_us_path).into_iter()
+            .filter_map(|e| e.ok())
+            .map(|e| pathdiff::diff_paths(e.path(), &among_us_path).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        remaining.sort();
+        std::fs::remove_dir_all(&among_us_path).unwrap();
+        // The plugin of a mod and a file that was not recorded stay
+        assert_eq!(remaining, vec!["", "BepInEx", "BepInEx/plugins", "BepInEx/plugins/Mod.dll", "changelog.txt"]);
+    }
+}
@@ -0,0 +1,288 @@
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+/// A setting of a BepInEx `.cfg` file together with the annotations BepInEx writes above it
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigSetting {
+    pub section : String,
+    pub key : String,
+    /// Boolean and number settings are returned as JSON booleans and numbers, everything else as a string
+    pub value : Value,
+    pub description : Option<String>,
+    /// The .NET type name from `# Setting type:`, like `Boolean`, `Int32` or the name of an enum
+    pub setting_type : Option<String>,
+    pub default_value : Option<String>,
+    pub acceptable_values : Option<Vec<String>>,
+    /// Enums marked with `[Flags]` accept multiple values separated by commas
+    pub allows_multiple_values : bool,
+    pub acceptable_range : Option<(f64, f64)>
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfigChange {
+    pub section : String,
+    pub key : String,
+    pub value : Value
+}
+
+/// A BepInEx config file. The lines are kept as they are, so writing settings only touches the changed values.
+pub struct ConfigFile {
+    lines : Vec<String>,
+    line_ending : &'static str,
+    ends_with_line_ending : bool
+}
+
+/// Splits `Key = Value` into the key, the position the value starts at and the value
+fn split_entry(line : &str) -> Option<(&str, usize, &str)> {
+    let equals_index = line.find('=')?;
+    let key = line[..equals_index].trim();
+    if key.is_empty() { return None; }
+    let after_equals = &line[equals_index + 1..];
+    let value_start = equals_index + 1 + (after_equals.len() - after_equals.trim_start().len());
+    Some((key, value_start, line[value_start..].trim_end()))
+}
+
+fn is_number_type(setting_type : &str) -> bool {
+    matches!(setting_type, "Byte" | "SByte" | "Int16" | "UInt16" | "Int32" | "UInt32" | "Int64" | "UInt64" | "Single" | "Double" | "Decimal")
+}
+
+fn is_integer_type(setting_type : &str) -> bool {
+    is_number_type(setting_type) && !matches!(setting_type, "Single" | "Double" | "Decimal")
+}
+
+/// Converts the text of a value into JSON depending on its type
+fn to_typed_value(value : &str, setting_type : Option<&str>) -> Value {
+    match setting_type {
+        Some("Boolean") => match value.to_lowercase().as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::String(value.to_string())
+        },
+        Some(t) if is_number_type(t) => value.parse::<serde_json::Number>()
+            .map(Value::Number)
+            .unwrap_or(Value::String(value.to_string())),
+        _ => Value::String(value.to_string())
+    }
+}
+
+impl ConfigSetting {
+    /// Checks a new value against the annotations and converts it into the text that is written to the file
+    fn format_value(&self, value : &Value) -> Result<String, String> {
+        let name = format!("{}.{}", self.section, self.key);
+        let text = match (self.setting_type.as_deref(), value) {
+            (Some("Boolean"), Value::Bool(b)) => b.to_string(),
+            (Some("Boolean"), _) => return Err(format!("{} has to be true or false", name)),
+            (Some(t), Value::Number(n)) if is_number_type(t) => {
+                if is_integer_type(t) && !(n.is_i64() || n.is_u64()) {
+                    return Err(format!("{} has to be a whole number", name));
+                }
+                if let (Some((min, max)), Some(n)) = (self.acceptable_range, n.as_f64()) {
+                    if n < min || n > max {
+                        return Err(format!("{} has to be between {} and {}", name, min, max));
+                    }
+                }
+                n.to_string()
+            },
+            (Some(t), _) if is_number_type(t) => return Err(format!("{} has to be a number", name)),
+            (_, Value::String(s)) => s.clone(),
+            (_, Value::Bool(b)) => b.to_string(),
+            (_, Value::Number(n)) => n.to_string(),
+            _ => return Err(format!("{} cannot be set to {}", name, value))
+        };
+        if text.contains('\n') || text.contains('\r') {
+            return Err(format!("{} cannot contain line breaks", name));
+        }
+        if let Some(acceptable_values) = &self.acceptable_values {
+            let values : Vec<&str> = if self.allows_multiple_values { text.split(',').map(|v| v.trim()).collect() } else { vec![text.as_str()] };
+            if let Some(value) = values.iter().find(|v| !acceptable_values.iter().any(|a| a == *v)) {
+                return Err(format!("{} is not an acceptable value for {}", value, name));
+            }
+        }
+        Ok(text)
+    }
+}
+
+impl ConfigFile {
+    pub fn parse(text : &str) -> Self {
+        let line_ending = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        let ends_with_line_ending = text.ends_with('\n');
+        let mut lines : Vec<String> = text.split('\n').map(|l| l.trim_end_matches('\r').to_string()).collect();
+        if ends_with_line_ending {
+            lines.pop();
+        }
+        Self { lines, line_ending, ends_with_line_ending }
+    }
+
+    pub fn read(path : &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).or(Err(format!("Cannot read {}", path.display())))?;
+        Ok(Self::parse(&text))
+    }
+
+    pub fn write(&self, path : &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_string()).or(Err(format!("Cannot write {}", path.display())))
+    }
+
+    /// Returns the settings together with the index of the line they are on
+    fn parse_settings(&self) -> Vec<(usize, ConfigSetting)> {
+        let mut settings = vec![];
+        let mut section = String::new();
+        let mut description : Vec<&str> = vec![];
+        let mut annotations : Vec<(&str, &str)> = vec![];
+        let mut allows_multiple_values = false;
+        for (index, line) in self.lines.iter().enumerate() {
+            let trimmed = line.trim();
+            if let Some(comment) = trimmed.strip_prefix("##") {
+                description.push(comment.trim());
+            } else if let Some(comment) = trimmed.strip_prefix('#') {
+                if comment.trim().starts_with("Multiple values can be set") {
+                    allows_multiple_values = true;
+                } else if let Some((name, value)) = comment.split_once(':') {
+                    annotations.push((name.trim(), value.trim()));
+                }
+            } else if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                section = trimmed[1..trimmed.len() - 1].trim().to_string();
+                description.clear();
+                annotations.clear();
+                allows_multiple_values = false;
+            } else if let Some((key, _, value)) = split_entry(line) {
+                let get_annotation = |name : &str| annotations.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.to_string());
+                let setting_type = get_annotation("Setting type");
+                let acceptable_range = get_annotation("Acceptable value range").and_then(|r| {
+                    let (min, max) = r.strip_prefix("From ")?.split_once(" to ")?;
+                    Some((min.trim().parse().ok()?, max.trim().parse().ok()?))
+                });
+                settings.push((index, ConfigSetting {
+                    section: section.clone(),
+                    key: key.to_string(),
+                    value: to_typed_value(value, setting_type.as_deref()),
+                    description: if description.is_empty() { None } else { Some(description.join("\n")) },
+                    default_value: get_annotation("Default value"),
+                    acceptable_values: get_annotation("Acceptable values").map(|v| v.split(',').map(|v| v.trim().to_string()).collect()),
+                    allows_multiple_values,
+                    acceptable_range,
+                    setting_type
+                }));
+                description.clear();
+                annotations.clear();
+                allows_multiple_values = false;
+            }
+        }
+        settings
+    }
+
+    pub fn get_settings(&self) -> Vec<ConfigSetting> {
+        self.parse_settings().into_iter().map(|(_, s)| s).collect()
+    }
+
+    /// Changes the value of an existing setting, keeping the key and the spacing around the `=` as they were
+    pub fn set(&mut self, section : &str, key : &str, value : &Value) -> Result<(), String> {
+        let (index, setting) = self.parse_settings().into_iter()
+            .find(|(_, s)| s.section == section && s.key == key)
+            .ok_or(format!("{}.{} does not exist", section, key))?;
+        let text = setting.format_value(value)?;
+        let line = &self.lines[index];
+        let (_, value_start, _) = split_entry(line).unwrap();
+        self.lines[index] = format!("{}{}", &line[..value_start], text);
+        Ok(())
+    }
+
+    /// Copies the values of an older version of the file into this one. Settings that don't exist anymore are dropped
+    /// and values that the new version doesn't accept are left at their new default. Returns how many values were kept.
+    pub fn merge_values(&mut self, old : &ConfigFile) -> usize {
+        let mut kept = 0;
+        for old_setting in old.get_settings() {
+            match self.set(&old_setting.section, &old_setting.key, &old_setting.value) {
+                Ok(_) => kept += 1,
+                Err(e) => log_info!("Not keeping old config value: {}", e)
+            }
+        }
+        kept
+    }
+}
+
+impl std::fmt::Display for ConfigFile {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.lines.join(self.line_ending))?;
+        if self.ends_with_line_ending {
+            write!(f, "{}", self.line_ending)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG : &str = "## Settings file was created by plugin Example v1.0.0\n\
+        ## Plugin GUID: com.example.plugin\n\
+        \n\
+        [General]\n\
+        \n\
+        ## Whether the plugin is active\n\
+        # Setting type: Boolean\n\
+        # Default value: true\n\
+        Enabled = true\n\
+        \n\
+        ## How fast the crewmates walk\n\
+        # Setting type: Single\n\
+        # Default value: 1\n\
+        # Acceptable value range: From 0.5 to 3\n\
+        Speed = 1.5\n\
+        \n\
+        [Other Section]\n\
+        \n\
+        # Setting type: String\n\
+        # Default value: \n\
+        Name=Red\n";
+
+    #[test]
+    fn writes_the_file_back_unchanged() {
+        assert_eq!(ConfigFile::parse(CONFIG).to_string(), CONFIG);
+        let windows_config = CONFIG.replace('\n', "\r\n");
+        assert_eq!(ConfigFile::parse(&windows_config).to_string(), windows_config);
+        let without_last_line_ending = CONFIG.trim_end();
+        assert_eq!(ConfigFile::parse(without_last_line_ending).to_string(), without_last_line_ending);
+    }
+
+    #[test]
+    fn keeps_comments_and_order_when_setting_values() {
+        let mut config = ConfigFile::parse(CONFIG);
+        config.set("General", "Speed", &serde_json::json!(2)).unwrap();
+        config.set("Other Section", "Name", &serde_json::json!("Blue")).unwrap();
+        let expected = CONFIG.replace("Speed = 1.5", "Speed = 2").replace("Name=Red", "Name=Blue");
+        assert_eq!(config.to_string(), expected);
+        let keys : Vec<String> = config.get_settings().into_iter().map(|s| s.key).collect();
+        assert_eq!(keys, vec!["Enabled", "Speed", "Name"]);
+    }
+
+    #[test]
+    fn rejects_values_outside_of_the_annotations() {
+        let mut config = ConfigFile::parse(CONFIG);
+        assert!(config.set("General", "Speed", &serde_json::json!(4)).is_err());
+        assert!(config.set("General", "Enabled", &serde_json::json!("yes")).is_err());
+        assert!(config.set("General", "Missing", &serde_json::json!(1)).is_err());
+        assert_eq!(config.to_string(), CONFIG);
+    }
+
+    #[test]
+    fn merges_the_values_of_an_older_version() {
+        let old_config = ConfigFile::parse("[General]\n\
+            # Setting type: Boolean\n\
+            Enabled = false\n\
+            # Setting type: Single\n\
+            Speed = 2.5\n\
+            # Setting type: Int32\n\
+            Removed = 3\n\
+            [Other Section]\n\
+            Name = Green\n");
+        // The new version only allows speeds up to 2
+        let new_text = CONFIG.replace("From 0.5 to 3", "From 0.5 to 2");
+        let mut config = ConfigFile::parse(&new_text);
+        let kept = config.merge_values(&old_config);
+        let expected = new_text.replace("Enabled = true", "Enabled = false").replace("Name=Red", "Name=Green");
+        assert_eq!(kept, 2);
+        assert_eq!(config.to_string(), expected);
+    }
+}
@@ -0,0 +1,243 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+use crate::sync;
+
+/// Blobs are written here first and renamed once complete, so a blob is either missing or whole
+const TEMPORARY_EXTENSION : &'static str = "tmp";
+const BLOB_EXTENSION : &'static str = "gz";
+
+/// Writes everything into a hasher, so data can be hashed while it is copied
+struct HashingWriter<W : Write> {
+    inner : W,
+    hasher : Sha256
+}
+
+impl<W : Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf : &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn is_hash(hash : &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+}
+
+/// Blobs are spread over folders named after the first two characters of their hash, so no folder gets too big
+fn get_blob_path(store_path : &Path, hash : &str) -> Result<PathBuf, String> {
+    if !is_hash(hash) {
+        return Err(format!("{} is not a SHA-256 hash", hash));
+    }
+    Ok(store_path.join(&hash[..2]).join(format!("{}.{}", hash, BLOB_EXTENSION)))
+}
+
+pub fn contains(store_path : &Path, hash : &str) -> bool {
+    get_blob_path(store_path, hash).map(|p| p.is_file()).unwrap_or(false)
+}
+
+/// Gives every blob that is being written its own temporary file, so two writes of the same content don't collide
+static NEXT_TEMPORARY_ID : AtomicUsize = AtomicUsize::new(0);
+
+/// Adds a file to the store, unless a file with the same content is already in it. Returns the hash of the file.
+pub fn put(store_path : &Path, source_path : &Path) -> Result<String, String> {
+    // The hash is usually cached already, so only new files are read twice
+    let hash = sync::hash_file_cached(source_path)?;
+    if get_blob_path(store_path, &hash)?.is_file() { return Ok(hash) }
+    std::fs::create_dir_all(store_path).or(Err(format!("Cannot create {}", store_path.display())))?;
+    let temporary_id = NEXT_TEMPORARY_ID.fetch_add(1, Ordering::Relaxed);
+    let temporary_path = store_path.join(format!("{}-{}.{}", std::process::id(), temporary_id, TEMPORARY_EXTENSION));
+    // The blob is named after what was actually compressed, in case the file changed after it was hashed
+    let write_blob = || -> std::io::Result<String> {
+        let mut source = BufReader::new(File::open(source_path)?);
+        let encoder = GzEncoder::new(BufWriter::new(File::create(&temporary_path)?), Compression::default());
+        let mut writer = HashingWriter { inner: encoder, hasher: Sha256::new() };
+        std::io::copy(&mut source, &mut writer)?;
+        writer.inner.finish()?.flush()?;
+        Ok(format!("{:x}", writer.hasher.finalize()))
+    };
+    let actual_hash = match write_blob() {
+        Ok(actual_hash) => actual_hash,
+        Err(e) => {
+            let _ = std::fs::remove_file(&temporary_path);
+            return Err(format!("Cannot store {}: {}", source_path.display(), e));
+        }
+    };
+    if actual_hash != hash {
+        log_error!("{} changed while it was stored, its hash is {} instead of {}", source_path.display(), actual_hash, hash);
+    }
+    let blob_path = get_blob_path(store_path, &actual_hash)?;
+    if blob_path.is_file() {
+        let _ = std::fs::remove_file(&temporary_path);
+        return Ok(actual_hash);
+    }
+    std::fs::create_dir_all(blob_path.parent().unwrap()).or(Err(format!("Cannot create {}", store_path.display())))?;
+    if std::fs::rename(&temporary_path, &blob_path).is_err() {
+        let _ = std::fs::remove_file(&temporary_path);
+        return Err(format!("Cannot store {}", source_path.display()));
+    }
+    Ok(actual_hash)
+}
+
+/// Decompresses a blob into a writer and returns the hash of what was written
+fn decompress<W : Write>(store_path : &Path, hash : &str, output : W) -> Result<String, String> {
+    let blob_path = get_blob_path(store_path, hash)?;
+    let file = File::open(&blob_path).or(Err(format!("The blob {} is missing", hash)))?;
+    let mut decoder = GzDecoder::new(BufReader::new(file));
+    let mut writer = HashingWriter { inner: output, hasher: Sha256::new() };
+    std::io::copy(&mut decoder, &mut writer).or(Err(format!("The blob {} is damaged", hash)))?;
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(format!("{:x}", writer.hasher.finalize()))
+}
+
+/// Writes the content of a blob to a file. The content is checked against its hash before the file is replaced.
+pub fn read_to(store_path : &Path, hash : &str, output_path : &Path) -> Result<(), String> {
+    let file_name = output_path.file_name().ok_or(format!("{} is not a file", output_path.display()))?;
+    let temporary_path = output_path.with_file_name(format!("{}.{}", file_name.to_string_lossy(), TEMPORARY_EXTENSION));
+    let output = File::create(&temporary_path).or(Err(format!("Cannot create {}", temporary_path.display())))?;
+    let result = decompress(store_path, hash, BufWriter::new(output));
+    match result {
+        Ok(actual_hash) if actual_hash == hash => {
+            std::fs::rename(&temporary_path, output_path).or(Err(format!("Cannot write {}", output_path.display())))
+        },
+        Ok(_) => {
+            let _ = std::fs::remove_file(&temporary_path);
+            Err(format!("The blob {} is damaged", hash))
+        },
+        Err(e) => {
+            let _ = std::fs::remove_file(&temporary_path);
+            Err(e)
+        }
+    }
+}
+
+/// Checks that a blob can be decompressed and still has the content it was stored with
+pub fn verify(store_path : &Path, hash : &str) -> bool {
+    decompress(store_path, hash, std::io::sink()).map(|h| h == hash).unwrap_or(false)
+}
+
+/// The hashes of all blobs and how much space they take up
+pub fn list(store_path : &Path) -> Vec<(String, u64)> {
+    WalkDir::new(store_path).into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| e.path().extension().map(|e| e == BLOB_EXTENSION).unwrap_or(false))
+        .filter_map(|e| {
+            let hash = e.path().file_stem()?.to_string_lossy().to_string();
+            if !is_hash(&hash) { return None; }
+            Some((hash, e.metadata().map(|m| m.len()).unwrap_or(0)))
+        })
+        .collect()
+}
+
+pub fn remove(store_path : &Path, hash : &str) -> Result<(), String> {
+    let blob_path = get_blob_path(store_path, hash)?;
+    std::fs::remove_file(&blob_path).or(Err(format!("Cannot remove {}", blob_path.display())))?;
+    // Empty prefix folders are removed as well, it fails if there are other blobs in it
+    let _ = std::fs::remove_dir(blob_path.parent().unwrap());
+    Ok(())
+}
+
+/// Removes blobs that were not completely written, because the launcher was closed while storing them
+pub fn remove_temporary_files(store_path : &Path) {
+    for entry in WalkDir::new(store_path).into_iter().filter_map(|e| e.ok()) {
+        if entry.file_type().is_file() && entry.path().extension().map(|e| e == TEMPORARY_EXTENSION).unwrap_or(false) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_temporary_folder(name : &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sussy_blob_store_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_back_what_was_put() {
+        let temporary_path = create_temporary_folder("round_trip");
+        let store_path = temporary_path.join("objects");
+        let content : Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        std::fs::write(temporary_path.join("file.bin"), &content).unwrap();
+        let hash = put(&store_path, &temporary_path.join("file.bin")).unwrap();
+        read_to(&store_path, &hash, &temporary_path.join("restored.bin")).unwrap();
+        let restored = std::fs::read(temporary_path.join("restored.bin")).unwrap();
+        let is_valid = verify(&store_path, &hash);
+        std::fs::remove_dir_all(&temporary_path).unwrap();
+        assert_eq!(hash, format!("{:x}", Sha256::digest(&content)));
+        assert_eq!(restored, content);
+        assert!(is_valid);
+    }
+
+    #[test]
+    fn stores_the_same_content_once() {
+        let temporary_path = create_temporary_folder("dedup");
+        let store_path = temporary_path.join("objects");
+        std::fs::write(temporary_path.join("first.txt"), "same content").unwrap();
+        std::fs::write(temporary_path.join("second.txt"), "same content").unwrap();
+        std::fs::write(temporary_path.join("other.txt"), "other content").unwrap();
+        let first_hash = put(&store_path, &temporary_path.join("first.txt")).unwrap();
+        let second_hash = put(&store_path, &temporary_path.join("second.txt")).unwrap();
+        let other_hash = put(&store_path, &temporary_path.join("other.txt")).unwrap();
+        let blobs = list(&store_path);
+        std::fs::remove_dir_all(&temporary_path).unwrap();
+        assert_eq!(first_hash, second_hash);
+        assert_ne!(first_hash, other_hash);
+        assert_eq!(blobs.len(), 2);
+    }
+
+    #[test]
+    fn rejects_a_damaged_blob() {
+        let temporary_path = create_temporary_folder("damaged");
+        let store_path = temporary_path.join("objects");
+        std::fs::write(temporary_path.join("file.txt"), "original content").unwrap();
+        std::fs::write(temporary_path.join("garbage.txt"), "not compressed").unwrap();
+        let hash = put(&store_path, &temporary_path.join("file.txt")).unwrap();
+        let blob_path = get_blob_path(&store_path, &hash).unwrap();
+        let output_path = temporary_path.join("restored.txt");
+        std::fs::write(&output_path, "left alone").unwrap();
+        // A blob that decompresses, but to different content
+        let mut encoder = GzEncoder::new(File::create(&blob_path).unwrap(), Compression::default());
+        encoder.write_all(b"changed content").unwrap();
+        encoder.finish().unwrap();
+        let changed_result = read_to(&store_path, &hash, &output_path);
+        let is_changed_valid = verify(&store_path, &hash);
+        // A blob that is not gzip at all
+        std::fs::copy(temporary_path.join("garbage.txt"), &blob_path).unwrap();
+        let garbage_result = read_to(&store_path, &hash, &output_path);
+        let is_garbage_valid = verify(&store_path, &hash);
+        let output = std::fs::read_to_string(&output_path).unwrap();
+        let is_temporary_left = output_path.with_extension(format!("txt.{}", TEMPORARY_EXTENSION)).exists();
+        std::fs::remove_dir_all(&temporary_path).unwrap();
+        assert_eq!(changed_result, Err(format!("The blob {} is damaged", hash)));
+        assert_eq!(garbage_result, Err(format!("The blob {} is damaged", hash)));
+        assert!(!is_changed_valid && !is_garbage_valid);
+        assert_eq!(output, "left alone");
+        assert!(!is_temporary_left);
+    }
+
+    #[test]
+    fn rejects_names_that_are_not_hashes() {
+        let store_path = Path::new("objects");
+        assert!(get_blob_path(store_path, "../../etc/passwd").is_err());
+        assert!(get_blob_path(store_path, &"A".repeat(64)).is_err());
+        assert!(!contains(store_path, "1234"));
+    }
+}
@@ -1,25 +1,55 @@
-use std::ffi::{CString};
 use serde::{Serialize, Deserialize};
 use std::path::{Path};
 use std::fs::{File};
+#[cfg(target_family = "windows")]
 use sysinfo::{DiskExt, SystemExt};
 use tauri::Window;
+#[cfg(target_family = "windows")]
 use walkdir::{WalkDir};
 use crate::mod_manager::Mod;
-use crate::{KNOWN_MODS, util};
+use crate::{archive, epic, itch, steam, KNOWN_MODS, util};
+use crate::dotnet::PluginInfo;
+use crate::version_info::VersionInfo;
+use crate::launch::{LaunchOptions, LaunchStrategy};
+use crate::session::SessionRecord;
+use crate::bepinex::BepInExSettings;
 
+#[cfg(target_family = "windows")]
 const AMONG_US_PATH_SKIP_DIRS : [&'static str; 26] = ["source", "videos", "images", "docs", "documents", "src", "music", "dev", "windows", "programdata", "lib", "library", "services", "service", "data", "sdk", "packs", "share", "shared", "doc", "required", "bin", "microsoft", "common files", "sysfiles", "content"];
+#[cfg(target_family = "windows")]
 const COMMON_AMONG_US_PATHS : [&'static str; 5] = ["Program Files/Steam/steamapps/common/Among Us/Among Us.exe", "Program Files (x86)/Steam/steamapps/common/Among Us/Among Us.exe", "Program Files/Epic Games/Among Us/Among Us.exe", "Program Files (x86)/Epic Games/Among Us/Among Us.exe", "SteamLibrary/steamapps/common/Among Us/Among Us.exe"];
 
-#[derive(Serialize, Deserialize)]
+/// Where the game was installed from, which decides how it has to be started
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Storefront {
+    Steam,
+    Epic,
+    Itch,
+    Unknown
+}
+
+impl Default for Storefront {
+    fn default() -> Self {
+        Storefront::Unknown
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Config {
     pub downloaded : Vec<Mod>,
     pub among_us_path : String,
     pub backup_among_us_path : String,
     pub mods_path : String,
-    pub run_with_steam : bool
+    #[serde(default)]
+    pub storefront : Storefront,
+    pub launch : LaunchOptions,
+    #[serde(default)]
+    pub session_history : Vec<SessionRecord>,
+    #[serde(default)]
+    pub bepinex : BepInExSettings
 }
 
+#[cfg(target_family = "windows")]
 fn is_among_us_path(path : &Path) -> bool {
     if !path.exists() {return false}
     if path.extension().is_none() { return false }
@@ -31,8 +61,60 @@ fn is_among_us_path(path : &Path) -> bool {
     return true
 }
 
+pub fn find_among_us_path(window : &Window) -> Option<(String, Storefront)> {
+    // Reading the files of the launchers is instant, so try that before searching the disks
+    window.emit("load", "Searching for Among Us in Steam libraries").unwrap();
+    if let Some(steam_install) = steam::find_among_us() {
+        log_info!("Found Among Us in the Steam library {}", steam_install.library_path.display());
+        return Some((steam_install.install_path.display().to_string(), Storefront::Steam));
+    }
+    window.emit("load", "Searching for Among Us in Epic Games manifests").unwrap();
+    if let Some(epic_install) = epic::find_among_us() {
+        log_info!("Found Among Us in the Epic Games manifest of {}", epic_install.app_name);
+        return Some((epic_install.install_path.display().to_string(), Storefront::Epic));
+    }
+    window.emit("load", "Searching for Among Us in itch.io installs").unwrap();
+    if let Some(itch_install) = itch::find_among_us() {
+        log_info!("Found Among Us in the itch.io cave {}", itch_install.cave_id);
+        return Some((itch_install.install_path.display().to_string(), Storefront::Itch));
+    }
+    let among_us_path = search_disks_for_among_us_path(window)?;
+    let storefront = detect_storefront(Path::new(&among_us_path));
+    Some((among_us_path, storefront))
+}
+
+/// Figures out where an already known install of the game came from
+pub fn detect_storefront(among_us_path : &Path) -> Storefront {
+    let is_same_path = |path : &Path| {
+        match (path.canonicalize(), among_us_path.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => path == among_us_path
+        }
+    };
+    if steam::find_among_us().map(|i| is_same_path(&i.install_path)).unwrap_or(false) {
+        return Storefront::Steam;
+    }
+    if epic::find_among_us().map(|i| is_same_path(&i.install_path)).unwrap_or(false) {
+        return Storefront::Epic;
+    }
+    if itch::find_among_us().map(|i| is_same_path(&i.install_path)).unwrap_or(false) {
+        return Storefront::Itch;
+    }
+    // Fall back to traces the launchers leave in the game folder
+    if among_us_path.join(".egstore").is_dir() {
+        return Storefront::Epic;
+    }
+    if among_us_path.join(".itch").is_dir() {
+        return Storefront::Itch;
+    }
+    if among_us_path.components().any(|c| c.as_os_str().to_string_lossy().eq_ignore_ascii_case("steamapps")) {
+        return Storefront::Steam;
+    }
+    Storefront::Unknown
+}
+
 #[cfg(target_family = "windows")]
-pub fn find_among_us_path(window : &Window) -> Option<String> {
+fn search_disks_for_among_us_path(window : &Window) -> Option<String> {
     let mut sys = sysinfo::System::new();
     sys.refresh_disks_list();
     let disks = sys.disks();
@@ -41,7 +123,7 @@ pub fn find_among_us_path(window : &Window) -> Option<String> {
         let disk_path = disk.mount_point();
         if let Some(among_us_path) = COMMON_AMONG_US_PATHS.iter().find_map(|p| {
             let path = disk_path.join(Path::new(p));
-            println!("{}", path.display());
+            log_info!("{}", path.display());
             if is_among_us_path(&path) {
                 Some(path)
             } else {
@@ -54,7 +136,7 @@ pub fn find_among_us_path(window : &Window) -> Option<String> {
     // Search basically everywhere
     for disk in disks {
         let disk_path = disk.mount_point();
-        println!("Searching disk : {}", disk.mount_point().to_str().unwrap());
+        log_info!("Searching disk : {}", disk.mount_point().to_str().unwrap());
         let among_us_path = WalkDir::new(disk_path).into_iter().filter_entry(|e| {
             if e.path().is_dir() {
                 !e.file_name().to_str().map(|s|
@@ -91,49 +173,42 @@ pub fn find_among_us_path(window : &Window) -> Option<String> {
 }
 
 #[cfg(target_family = "unix")]
-fn find_among_us_path(window : &Window) -> Option<String> {
-    None
-}
-
-#[cfg(target_family = "windows")]
-unsafe fn get_dll_version_number<T: AsRef<Path>>(path: T) -> Option<(u16, u16, u16, u16)> {
-    let Ok(cstring_path) = CString::new(path.as_ref().display().to_string()) else {return None;};
-    let path_pointer = cstring_path.as_ptr() as *const i8;
-    let file_version_info_size = winapi::um::winver::GetFileVersionInfoSizeA(path_pointer, std::ptr::null_mut());
-    if file_version_info_size == 0 {return None;}
-    let mut buffer: Vec<u8> = (0..file_version_info_size).map(|_| 0).collect();
-    if winapi::um::winver::GetFileVersionInfoA(
-        path_pointer,
-        0,
-        file_version_info_size,
-        buffer.as_mut_ptr() as *mut winapi::ctypes::c_void
-    ) == 0 { return None; }
-    let minor = (buffer[47] as u16) << 8 | (buffer[48] as u16);
-    let major = (buffer[49] as u16) << 8 | (buffer[50] as u16);
-    let revision = (buffer[51] as u16) << 8 | (buffer[52] as u16);
-    let build = (buffer[53] as u16) << 8 | (buffer[54] as u16);
-    Some((major, minor, build, revision))
-}
-
-#[cfg(target_family = "unix")]
-unsafe fn get_dll_version_number<T: AsRef<Path>>(path: T) -> Option<(u16, u16, u16, u16)> {
+fn search_disks_for_among_us_path(_window : &Window) -> Option<String> {
     None
 }
 
 impl Config {
 
+    /// Called after the game was restored to the vanilla game, which removed everything the launcher installed
+    pub fn forget_installed_files(&mut self) {
+        self.bepinex.forget_install();
+        for modification in &mut self.downloaded {
+            modification.installed_files = Some(vec![]);
+        }
+    }
+
     pub fn load() -> Self {
         let config_path = Path::new("sussy_launcher.json");
         if config_path.exists() {
             let file = File::open(config_path).unwrap();
-            serde_json::from_reader(file).unwrap()
+            let mut value : serde_json::Value = serde_json::from_reader(file).unwrap();
+            // Older versions only had a flag for starting the game through Steam
+            if value.get("launch").is_none() {
+                let run_with_steam = value.get("run_with_steam").and_then(|r| r.as_bool()).unwrap_or(true);
+                let strategy = if run_with_steam { LaunchStrategy::SteamUri } else { LaunchStrategy::Executable };
+                value["launch"] = serde_json::to_value(LaunchOptions::new(strategy)).unwrap();
+            }
+            serde_json::from_value(value).unwrap()
         } else {
             let config = Config {
                 downloaded: vec![],
                 among_us_path: "".to_string(),
                 backup_among_us_path: "./backup".to_string(),
                 mods_path : "./mods".to_string(),
-                run_with_steam : true
+                storefront : Storefront::Unknown,
+                launch : LaunchOptions::new(LaunchStrategy::SteamUri),
+                session_history : vec![],
+                bepinex : BepInExSettings::default()
             };
             config.save();
             config
@@ -152,19 +227,32 @@ impl Config {
         let Some(known_mods) = KNOWN_MODS.get() else {return;};
         for entry in read_dir.filter_map(|i| i.ok()) {
             let path = entry.path();
-            let Some(file_stem) = path.file_stem() else {continue};
+            let Some(file_stem) = path.file_stem().and_then(|s| s.to_str()) else {continue};
             let Some(file_extension) = path.extension().and_then(|p| p.to_str()) else {continue};
             if file_extension != "dll" {continue;}
-            let dll_mod_version = unsafe {get_dll_version_number(&path)};
-            let installed_mod_version_string = if let Some(v) = dll_mod_version { format!("{}.{}.{}", v.0, v.1, v.2) } else {"0.0.0".to_string()};
-            let mut modification = match known_mods.iter().find(|m| m.name.to_lowercase().as_str() == file_stem.to_ascii_lowercase()) {
+            // Identify the mod by the metadata of its plugin if possible, because the file name can be anything
+            let plugin = PluginInfo::read_file(&path).ok().and_then(|p| p.into_iter().next());
+            let installed_mod_version_string = match &plugin {
+                Some(plugin) => plugin.version.clone(),
+                None => {
+                    let dll_mod_version = VersionInfo::read_file(&path).ok().and_then(|v| v.file_version);
+                    if let Some(v) = dll_mod_version { format!("{}.{}.{}", v.0, v.1, v.2) } else {"0.0.0".to_string()}
+                }
+            };
+            let is_same_name = |name : &str| name.to_lowercase() == file_stem.to_lowercase() ||
+                plugin.as_ref().map(|p| p.name.to_lowercase() == name.to_lowercase()).unwrap_or(false);
+            // Make sure the mod is not already "installed"
+            if self.downloaded.iter().any(|m| {
+                is_same_name(&m.name) || plugin.as_ref().map(|p| m.plugin_guids.contains(&p.guid)).unwrap_or(false)
+            }) {
+                continue;
+            }
+            let known_mod = known_mods.iter().find(|m| match (&m.guid, &plugin) {
+                (Some(guid), Some(plugin)) if guid == &plugin.guid => true,
+                _ => is_same_name(&m.name)
+            });
+            let mut modification = match known_mod {
                 Some(known_mod) => {
-                    // Make sure the mod is not already "installed"
-                    if self.downloaded.iter()
-                        .find(|m| m.name.to_lowercase().as_str() == file_stem.to_ascii_lowercase())
-                        .is_some() {
-                            continue;
-                    }
                     let Ok(mut modification) = Mod::new(
                         known_mod.name.clone(),
                         &known_mod.location,
@@ -175,8 +263,12 @@ impl Config {
                     modification
                 },
                 None => {
+                    let name = match &plugin {
+                        Some(plugin) if archive::check_name(&plugin.name).is_ok() && plugin.name.len() >= 3 => plugin.name.clone(),
+                        _ => file_stem.to_string()
+                    };
                     let Ok(mut modification) = Mod::new(
-                        file_stem.to_str().unwrap().to_string(),
+                        name,
                         &path.display().to_string(),
                         installed_mod_version_string
                     ).await else {continue;};
@@ -184,6 +276,11 @@ impl Config {
                     modification
                 }
             };
+            if let Some(plugin) = plugin {
+                modification.plugin_guids = vec![plugin.guid];
+            }
+            // The mod was installed by hand, so which files belong to it is unknown
+            modification.installed_files = None;
             modification.enabled = true;
             self.downloaded.push(modification);
         }
@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use serde_json::Value;
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use crate::config::Config;
+use crate::{backup, bepinex, launcher_log, logs, util};
+
+/// Only the end of each log is included, that's where the interesting part is
+const MAX_LOG_SIZE : u64 = 1024 * 1024;
+const SESSION_HISTORY_LENGTH : usize = 10;
+
+#[derive(Debug, Serialize)]
+struct InstalledFile {
+    path : String,
+    size : u64,
+    sha256 : Option<String>
+}
+
+#[derive(Debug, Serialize)]
+struct DiagnosticsSummary {
+    launcher_version : &'static str,
+    os : &'static str,
+    storefront : String,
+    game_build : Option<backup::GameBuild>,
+    snapshots : Vec<backup::SnapshotInfo>,
+    launch_command : Result<String, String>,
+    bepinex : bepinex::BepInExStatus,
+    session_history : Vec<crate::session::SessionRecord>
+}
+
+/// The home folder contains the user name, so it is replaced in everything that goes into the bundle
+fn get_home_path() -> Option<String> {
+    std::env::var("HOME").or(std::env::var("USERPROFILE")).ok().filter(|h| h.len() > 1)
+}
+
+fn redact_text(text : &str) -> String {
+    match get_home_path() {
+        Some(home) => text.replace(&home, "<home>"),
+        None => text.to_string()
+    }
+}
+
+fn redact_value(value : &mut Value) {
+    match value {
+        Value::String(s) => *s = redact_text(s),
+        Value::Array(values) => values.iter_mut().for_each(redact_value),
+        Value::Object(map) => map.values_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+fn redact_config(config : &Config) -> Result<Value, String> {
+    let mut value = serde_json::to_value(config).map_err(|e| e.to_string())?;
+    // Environment variables can contain tokens
+    if let Some(Value::Object(env)) = value.pointer_mut("/launch/env") {
+        env.values_mut().for_each(|v| *v = Value::String("<redacted>".to_string()));
+    }
+    redact_value(&mut value);
+    Ok(value)
+}
+
+fn read_log_tail(path : &Path) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let length = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(length.saturating_sub(MAX_LOG_SIZE))).ok()?;
+    let mut data = vec![];
+    file.read_to_end(&mut data).ok()?;
+    Some(redact_text(&String::from_utf8_lossy(&data)))
+}
+
+fn list_files(folder : &Path) -> Vec<InstalledFile> {
+    WalkDir::new(folder).into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| InstalledFile {
+            path: pathdiff::diff_paths(e.path(), folder).unwrap_or(e.path().to_path_buf()).display().to_string(),
+            size: e.metadata().map(|m| m.len()).unwrap_or(0),
+            sha256: util::hash_file(e.path()).ok()
+        })
+        .collect()
+}
+
+fn get_mod_list(config : &Config) -> Result<Value, String> {
+    let mut mods = vec![];
+    for modification in &config.downloaded {
+        let mut value = serde_json::to_value(modification).map_err(|e| e.to_string())?;
+        let plugin_files : Vec<InstalledFile> = modification.get_plugin_dll_paths(config).iter().map(|p| InstalledFile {
+            path: p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+            size: p.metadata().map(|m| m.len()).unwrap_or(0),
+            sha256: util::hash_file(p).ok()
+        }).collect();
+        value["plugin_files"] = serde_json::to_value(plugin_files).map_err(|e| e.to_string())?;
+        mods.push(value);
+    }
+    let mut value = serde_json::json!({
+        "mods": mods,
+        // What is actually in the game folder can differ from what the launcher thinks it installed
+        "installed_plugins": list_files(&util::get_plugins_path(config))
+    });
+    redact_value(&mut value);
+    Ok(value)
+}
+
+fn write_zip_entry(zip : &mut zip::ZipWriter<File>, name : &str, data : &[u8]) -> Result<(), String> {
+    zip.start_file(name, FileOptions::default()).or(Err(format!("Cannot add {} to the diagnostics bundle", name)))?;
+    zip.write_all(data).or(Err(format!("Cannot add {} to the diagnostics bundle", name)))
+}
+
+fn to_pretty_json<T : Serialize>(value : &T) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(value).map_err(|e| e.to_string())
+}
+
+/// Writes everything needed to debug a broken install into one zip file
+pub fn export(config : &Config, output_path : &Path) -> Result<(), String> {
+    let among_us_path = Path::new(&config.among_us_path);
+    let file = File::create(output_path).or(Err(format!("Cannot create {}", output_path.display())))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let summary = DiagnosticsSummary {
+        launcher_version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        storefront: format!("{:?}", config.storefront),
+        game_build: backup::detect_build(among_us_path, config.storefront),
+        snapshots: backup::list_snapshots(Path::new(&config.backup_among_us_path)),
+        launch_command: config.launch.build_command(among_us_path).map(|c| redact_text(&c.to_string())),
+        bepinex: bepinex::verify(&config.bepinex, among_us_path),
+        session_history: config.session_history.iter().rev().take(SESSION_HISTORY_LENGTH).cloned().collect()
+    };
+    write_zip_entry(&mut zip, "summary.json", &to_pretty_json(&summary)?)?;
+    write_zip_entry(&mut zip, "sussy_launcher.json", &to_pretty_json(&redact_config(config)?)?)?;
+    write_zip_entry(&mut zip, "mods.json", &to_pretty_json(&get_mod_list(config)?)?)?;
+    let mut log_paths : Vec<(String, PathBuf)> = logs::get_log_paths(config).into_iter()
+        .map(|(_, path)| (path.file_name().unwrap().to_string_lossy().to_string(), path))
+        .collect();
+    // Unity keeps the log of the previous run next to the current one
+    if let Some((_, player_log_path)) = log_paths.iter().find(|(n, _)| n == "Player.log").cloned() {
+        log_paths.push(("Player-prev.log".to_string(), player_log_path.with_file_name("Player-prev.log")));
+    }
+    log_paths.push((launcher_log::LOG_PATH.to_string(), PathBuf::from(launcher_log::LOG_PATH)));
+    log_paths.push((launcher_log::PREVIOUS_LOG_PATH.to_string(), PathBuf::from(launcher_log::PREVIOUS_LOG_PATH)));
+    for (name, path) in log_paths {
+        if let Some(log) = read_log_tail(&path) {
+            write_zip_entry(&mut zip, &format!("logs/{}", name), log.as_bytes())?;
+        }
+    }
+    zip.finish().or(Err(format!("Cannot write {}", output_path.display())))?;
+    Ok(())
+}
@@ -0,0 +1,278 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use tauri::Window;
+use walkdir::WalkDir;
+use crate::config::{self, Config};
+use crate::launch::{LaunchCommand, LaunchStrategy};
+use crate::mod_manager::ModType;
+use crate::{backup, bepinex, session, sync, util, wine, BackupLock};
+
+const GITHUB_RATE_LIMIT_URL : &'static str = "https://api.github.com/rate_limit";
+const GITHUB_TIMEOUT_SECS : u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail
+}
+
+/// Remedies for problems, that can be applied without the user having to do anything
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Fix {
+    DetectAmongUsPath,
+    CreateBackup,
+    ReinstallBepInEx,
+    RemoveOrphanedPlugins,
+    DownloadMissingMods
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name : &'static str,
+    pub status : CheckStatus,
+    pub message : String,
+    /// What the user can do about a problem
+    pub suggestion : Option<String>,
+    pub fix : Option<Fix>
+}
+
+impl CheckResult {
+    fn pass(name : &'static str, message : impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Pass, message: message.into(), suggestion: None, fix: None }
+    }
+
+    fn warn(name : &'static str, message : impl Into<String>, suggestion : impl Into<String>, fix : Option<Fix>) -> Self {
+        Self { name, status: CheckStatus::Warn, message: message.into(), suggestion: Some(suggestion.into()), fix }
+    }
+
+    fn fail(name : &'static str, message : impl Into<String>, suggestion : impl Into<String>, fix : Option<Fix>) -> Self {
+        Self { name, status: CheckStatus::Fail, message: message.into(), suggestion: Some(suggestion.into()), fix }
+    }
+}
+
+fn check_among_us_path(config : &Config) -> CheckResult {
+    const NAME : &'static str = "Among Us folder";
+    if config.among_us_path.is_empty() {
+        return CheckResult::fail(NAME, "The Among Us folder is not set", "Search for the game again", Some(Fix::DetectAmongUsPath));
+    }
+    let among_us_path = Path::new(&config.among_us_path);
+    if !among_us_path.is_dir() {
+        return CheckResult::fail(NAME, format!("{} does not exist", among_us_path.display()), "Search for the game again", Some(Fix::DetectAmongUsPath));
+    }
+    if !among_us_path.join("Among Us.exe").is_file() {
+        return CheckResult::fail(
+            NAME,
+            format!("Among Us.exe is missing in {}", among_us_path.display()),
+            "Verify the game files through your storefront or search for the game again",
+            Some(Fix::DetectAmongUsPath)
+        );
+    }
+    CheckResult::pass(NAME, format!("Among Us is installed in {}", among_us_path.display()))
+}
+
+fn check_backup(config : &Config) -> CheckResult {
+    const NAME : &'static str = "Backup";
+    let suggestion = "Temporary sessions and restoring the game need a backup of the installed build";
+    let snapshot_path = match backup::resolve_snapshot_path(config, None) {
+        Ok(snapshot_path) => snapshot_path,
+        Err(e) => return CheckResult::fail(NAME, e, suggestion, None)
+    };
+    let build_id = snapshot_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    if backup::is_complete(&snapshot_path) {
+        return CheckResult::pass(NAME, format!("The vanilla game {} is backed up in {}", build_id, snapshot_path.display()));
+    }
+    let message = if snapshot_path.exists() {
+        format!("The backup of {} is incomplete", build_id)
+    } else {
+        format!("There is no backup of {}, the game might have been updated", build_id)
+    };
+    CheckResult::warn(NAME, message, suggestion, Some(Fix::CreateBackup))
+}
+
+fn check_bepinex(config : &Config) -> CheckResult {
+    const NAME : &'static str = "BepInEx";
+    let among_us_path = Path::new(&config.among_us_path);
+    let status = bepinex::verify(&config.bepinex, among_us_path);
+    if !status.installed {
+        // Mods that come with files usually bring their own BepInEx, dll mods don't
+        let needs_bepinex = config.downloaded.iter().any(|m| m.enabled && matches!(m.mod_type, ModType::Dll));
+        if needs_bepinex {
+            return CheckResult::fail(NAME, "BepInEx is not installed, but dll mods are enabled", "Install BepInEx", Some(Fix::ReinstallBepInEx));
+        }
+        return CheckResult::pass(NAME, "BepInEx is not installed and not needed");
+    }
+    if !status.problems.is_empty() {
+        return CheckResult::fail(
+            NAME,
+            format!("BepInEx is only partially installed: {}", status.problems.join(", ")),
+            "Reinstall BepInEx, plugins and their configs are kept",
+            Some(Fix::ReinstallBepInEx)
+        );
+    }
+    let version = status.core_version.or(status.installed_version).unwrap_or("an unknown version".to_string());
+    CheckResult::pass(NAME, format!("BepInEx {} is installed", version))
+}
+
+/// Plugin dlls that none of the known mods put there, for example leftovers from mods installed by hand
+pub fn find_orphaned_plugins(config : &Config) -> Vec<PathBuf> {
+    let plugins_path = util::get_plugins_path(config);
+    let known_files : HashSet<PathBuf> = config.downloaded.iter()
+        .flat_map(|m| m.get_plugin_files(config))
+        .collect();
+    WalkDir::new(&plugins_path).into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| e.path().extension().map(|e| e.eq_ignore_ascii_case("dll")).unwrap_or(false))
+        .filter(|e| pathdiff::diff_paths(e.path(), &plugins_path).map(|p| !known_files.contains(&p)).unwrap_or(false))
+        .map(|e| e.path().to_path_buf())
+        .collect()
+}
+
+fn check_orphaned_plugins(config : &Config) -> CheckResult {
+    const NAME : &'static str = "Plugins";
+    let orphans = find_orphaned_plugins(config);
+    if orphans.is_empty() {
+        return CheckResult::pass(NAME, "Every plugin belongs to a mod");
+    }
+    let names : Vec<String> = orphans.iter().filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string())).collect();
+    CheckResult::warn(
+        NAME,
+        format!("{} plugins do not belong to any mod: {}", orphans.len(), names.join(", ")),
+        "Remove them or add them as mods, they are loaded even though the launcher can't manage them",
+        Some(Fix::RemoveOrphanedPlugins)
+    )
+}
+
+fn check_mods_downloaded(config : &Config) -> CheckResult {
+    const NAME : &'static str = "Mods";
+    let missing : Vec<&str> = config.downloaded.iter()
+        .filter(|m| m.enabled && !m.is_downloaded(config))
+        .map(|m| m.name.as_str())
+        .collect();
+    if missing.is_empty() {
+        return CheckResult::pass(NAME, format!("{} mods are downloaded", config.downloaded.len()));
+    }
+    CheckResult::fail(
+        NAME,
+        format!("These mods are missing in {}: {}", config.mods_path, missing.join(", ")),
+        "Download them again",
+        Some(Fix::DownloadMissingMods)
+    )
+}
+
+fn check_launch(config : &Config) -> CheckResult {
+    const NAME : &'static str = "Launch";
+    let command = match config.launch.build_command(Path::new(&config.among_us_path)) {
+        Ok(command) => command,
+        Err(e) => return CheckResult::fail(NAME, e, "Change the launch options", None)
+    };
+    if let LaunchCommand::Process { program, .. } = &command {
+        // Programs without a path are looked up like a shell would
+        let exists = if Path::new(program).components().count() > 1 {
+            Path::new(program).is_file()
+        } else {
+            wine::find_in_path(program).is_some()
+        };
+        if !exists {
+            return CheckResult::fail(NAME, format!("{} does not exist", program), "Change the launch options", None);
+        }
+    }
+    CheckResult::pass(NAME, format!("The game is started with: {}", command))
+}
+
+async fn check_github() -> CheckResult {
+    const NAME : &'static str = "GitHub";
+    let client = util::get_reqwest_client();
+    let response = client.get(GITHUB_RATE_LIMIT_URL)
+        .timeout(std::time::Duration::from_secs(GITHUB_TIMEOUT_SECS))
+        .send()
+        .await;
+    let suggestion = "Mods and BepInEx are downloaded from GitHub, check your internet connection and firewall";
+    let response = match response {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => return CheckResult::fail(NAME, format!("GitHub returned status code {}", response.status().as_u16()), suggestion, None),
+        Err(e) => return CheckResult::fail(NAME, format!("GitHub is not reachable: {}", e), suggestion, None)
+    };
+    let remaining = response.json::<serde_json::Value>().await.ok()
+        .and_then(|v| v.pointer("/rate/remaining").and_then(|r| r.as_u64()));
+    match remaining {
+        Some(0) => CheckResult::warn(NAME, "The GitHub API rate limit is used up", "Wait an hour before updating mods", None),
+        _ => CheckResult::pass(NAME, "GitHub is reachable")
+    }
+}
+
+fn check_game_not_running() -> CheckResult {
+    const NAME : &'static str = "Game";
+    if session::is_game_running() {
+        return CheckResult::warn(NAME, "Among Us is running", "Close it, mods can't be changed while it runs", None);
+    }
+    CheckResult::pass(NAME, "Among Us is not running")
+}
+
+/// Runs all checks. Checks that need a working game folder are skipped if there is none.
+pub async fn run(config : &Config) -> Vec<CheckResult> {
+    let among_us_path_check = check_among_us_path(config);
+    let has_game = among_us_path_check.status == CheckStatus::Pass;
+    let mut results = vec![among_us_path_check];
+    if has_game {
+        results.push(check_backup(config));
+        results.push(check_bepinex(config));
+        results.push(check_orphaned_plugins(config));
+    }
+    results.push(check_mods_downloaded(config));
+    if has_game {
+        results.push(check_launch(config));
+    }
+    results.push(check_game_not_running());
+    results.push(check_github().await);
+    results
+}
+
+pub async fn apply_fix(fix : Fix, config : &mut Config, backup_lock : &BackupLock, window : &Window) -> Result<(), String> {
+    let among_us_path = PathBuf::from(&config.among_us_path);
+    match fix {
+        Fix::DetectAmongUsPath => {
+            let (among_us_path, storefront) = config::find_among_us_path(window).ok_or("Among Us could not be found")?;
+            config.among_us_path = among_us_path;
+            config.storefront = storefront;
+            config.launch.strategy = LaunchStrategy::for_storefront(storefront);
+        },
+        Fix::CreateBackup => {
+            session::ensure_game_not_running()?;
+            let source = backup::BackupSource::new(config);
+            let _backup_guard = backup_lock.lock().await;
+            sync::run_blocking(move || backup::create(&source)).await?;
+        },
+        Fix::ReinstallBepInEx => {
+            session::ensure_game_not_running()?;
+            let mut settings = config.bepinex.clone();
+            // Forces the download, even if the launcher thinks the version is already installed
+            settings.installed_version = None;
+            if let Some(install) = bepinex::update(&settings, &among_us_path, window).await? {
+                config.bepinex.record_install(install);
+            }
+        },
+        Fix::RemoveOrphanedPlugins => {
+            session::ensure_game_not_running()?;
+            for path in find_orphaned_plugins(config) {
+                log_info!("Removing orphaned plugin {}", path.display());
+                std::fs::remove_file(&path).or(Err(format!("Cannot remove {}", path.display())))?;
+            }
+        },
+        Fix::DownloadMissingMods => {
+            for index in 0..config.downloaded.len() {
+                if !config.downloaded[index].enabled || config.downloaded[index].is_downloaded(config) { continue; }
+                let mut modification = std::mem::take(&mut config.downloaded[index]);
+                window.emit("progress", format!("Downloading {}", modification.name)).unwrap();
+                let result = modification.download(config, window).await;
+                config.downloaded[index] = modification;
+                result?;
+            }
+        }
+    }
+    config.save();
+    Ok(())
+}
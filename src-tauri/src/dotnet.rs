@@ -0,0 +1,417 @@
+use serde::Serialize;
+use crate::pe::{read_u16, read_u32, PeFile, IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR};
+
+const METADATA_SIGNATURE : u32 = 0x424A5342;
+const TABLE_COUNT : usize = 64;
+
+pub const TABLE_TYPE_REF : usize = 0x01;
+pub const TABLE_MEMBER_REF : usize = 0x0A;
+pub const TABLE_CUSTOM_ATTRIBUTE : usize = 0x0C;
+pub const TABLE_ASSEMBLY : usize = 0x20;
+pub const TABLE_ASSEMBLY_REF : usize = 0x23;
+
+#[derive(Debug, Clone, Copy)]
+enum Column {
+    U16,
+    U32,
+    Strings,
+    Guid,
+    Blob,
+    Table(usize),
+    Coded(CodedIndex)
+}
+
+/// Coded indexes point into one of several tables, selected by the lowest bits
+#[derive(Debug, Clone, Copy)]
+enum CodedIndex {
+    TypeDefOrRef,
+    HasConstant,
+    HasCustomAttribute,
+    HasFieldMarshal,
+    HasDeclSecurity,
+    MemberRefParent,
+    HasSemantics,
+    MethodDefOrRef,
+    MemberForwarded,
+    Implementation,
+    CustomAttributeType,
+    ResolutionScope,
+    TypeOrMethodDef
+}
+
+impl CodedIndex {
+    /// Returns the number of tag bits and the tables that can be referenced. `None` marks unused tags.
+    fn tables(&self) -> (u32, &'static [Option<usize>]) {
+        match self {
+            CodedIndex::TypeDefOrRef => (2, &[Some(0x02), Some(0x01), Some(0x1B)]),
+            CodedIndex::HasConstant => (2, &[Some(0x04), Some(0x08), Some(0x17)]),
+            CodedIndex::HasCustomAttribute => (5, &[
+                Some(0x06), Some(0x04), Some(0x01), Some(0x02), Some(0x08), Some(0x09), Some(0x0A), Some(0x00), Some(0x0E), Some(0x17), Some(0x14),
+                Some(0x11), Some(0x1A), Some(0x1B), Some(0x20), Some(0x23), Some(0x26), Some(0x27), Some(0x28), Some(0x2A), Some(0x2C), Some(0x2B)
+            ]),
+            CodedIndex::HasFieldMarshal => (1, &[Some(0x04), Some(0x08)]),
+            CodedIndex::HasDeclSecurity => (2, &[Some(0x02), Some(0x06), Some(0x20)]),
+            CodedIndex::MemberRefParent => (3, &[Some(0x02), Some(0x01), Some(0x1A), Some(0x06), Some(0x1B)]),
+            CodedIndex::HasSemantics => (1, &[Some(0x14), Some(0x17)]),
+            CodedIndex::MethodDefOrRef => (1, &[Some(0x06), Some(0x0A)]),
+            CodedIndex::MemberForwarded => (1, &[Some(0x04), Some(0x06)]),
+            CodedIndex::Implementation => (2, &[Some(0x26), Some(0x23), Some(0x27)]),
+            CodedIndex::CustomAttributeType => (3, &[None, None, Some(0x06), Some(0x0A), None]),
+            CodedIndex::ResolutionScope => (2, &[Some(0x00), Some(0x1A), Some(0x23), Some(0x01)]),
+            CodedIndex::TypeOrMethodDef => (1, &[Some(0x02), Some(0x06)])
+        }
+    }
+}
+
+/// Columns of every table, that can appear in an assembly
+fn get_table_schema(table : usize) -> Option<&'static [Column]> {
+    use Column::*;
+    use CodedIndex::*;
+    Some(match table {
+        0x00 => &[U16, Strings, Guid, Guid, Guid], // Module
+        0x01 => &[Coded(ResolutionScope), Strings, Strings], // TypeRef
+        0x02 => &[U32, Strings, Strings, Coded(TypeDefOrRef), Table(0x04), Table(0x06)], // TypeDef
+        0x03 => &[Table(0x04)], // FieldPtr
+        0x04 => &[U16, Strings, Blob], // Field
+        0x05 => &[Table(0x06)], // MethodPtr
+        0x06 => &[U32, U16, U16, Strings, Blob, Table(0x08)], // MethodDef
+        0x07 => &[Table(0x08)], // ParamPtr
+        0x08 => &[U16, U16, Strings], // Param
+        0x09 => &[Table(0x02), Coded(TypeDefOrRef)], // InterfaceImpl
+        0x0A => &[Coded(MemberRefParent), Strings, Blob], // MemberRef
+        0x0B => &[U16, Coded(HasConstant), Blob], // Constant
+        0x0C => &[Coded(HasCustomAttribute), Coded(CustomAttributeType), Blob], // CustomAttribute
+        0x0D => &[Coded(HasFieldMarshal), Blob], // FieldMarshal
+        0x0E => &[U16, Coded(HasDeclSecurity), Blob], // DeclSecurity
+        0x0F => &[U16, U32, Table(0x02)], // ClassLayout
+        0x10 => &[U32, Table(0x04)], // FieldLayout
+        0x11 => &[Blob], // StandAloneSig
+        0x12 => &[Table(0x02), Table(0x14)], // EventMap
+        0x13 => &[Table(0x14)], // EventPtr
+        0x14 => &[U16, Strings, Coded(TypeDefOrRef)], // Event
+        0x15 => &[Table(0x02), Table(0x17)], // PropertyMap
+        0x16 => &[Table(0x17)], // PropertyPtr
+        0x17 => &[U16, Strings, Blob], // Property
+        0x18 => &[U16, Table(0x06), Coded(HasSemantics)], // MethodSemantics
+        0x19 => &[Table(0x02), Coded(MethodDefOrRef), Coded(MethodDefOrRef)], // MethodImpl
+        0x1A => &[Strings], // ModuleRef
+        0x1B => &[Blob], // TypeSpec
+        0x1C => &[U16, Coded(MemberForwarded), Strings, Table(0x1A)], // ImplMap
+        0x1D => &[U32, Table(0x04)], // FieldRVA
+        0x1E => &[U32, U32], // EncLog
+        0x1F => &[U32], // EncMap
+        0x20 => &[U32, U16, U16, U16, U16, U32, Blob, Strings, Strings], // Assembly
+        0x21 => &[U32], // AssemblyProcessor
+        0x22 => &[U32, U32, U32], // AssemblyOS
+        0x23 => &[U16, U16, U16, U16, U32, Blob, Strings, Strings, Blob], // AssemblyRef
+        0x24 => &[U32, Table(0x23)], // AssemblyRefProcessor
+        0x25 => &[U32, U32, U32, Table(0x23)], // AssemblyRefOS
+        0x26 => &[U32, Strings, Blob], // File
+        0x27 => &[U32, U32, Strings, Strings, Coded(Implementation)], // ExportedType
+        0x28 => &[U32, U32, Strings, Coded(Implementation)], // ManifestResource
+        0x29 => &[Table(0x02), Table(0x02)], // NestedClass
+        0x2A => &[U16, U16, Coded(TypeOrMethodDef), Strings], // GenericParam
+        0x2B => &[Coded(MethodDefOrRef), Blob], // MethodSpec
+        0x2C => &[Table(0x2A), Coded(TypeDefOrRef)], // GenericParamConstraint
+        _ => return None
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+struct TableInfo {
+    rows : u32,
+    offset : usize,
+    row_size : usize,
+    column_offsets : Vec<usize>,
+    column_sizes : Vec<usize>
+}
+
+/// Reads an unsigned integer, that is compressed as described in ECMA-335 II.23.2.
+/// Returns the value and the amount of bytes it took up.
+pub fn read_compressed_u32(data : &[u8], offset : usize) -> Option<(u32, usize)> {
+    let first = *data.get(offset)? as u32;
+    if first & 0x80 == 0 {
+        Some((first, 1))
+    } else if first & 0xC0 == 0x80 {
+        Some((((first & 0x3F) << 8) | *data.get(offset + 1)? as u32, 2))
+    } else if first & 0xE0 == 0xC0 {
+        let bytes = data.get(offset + 1..offset + 4)?;
+        Some((((first & 0x1F) << 24) | (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32, 4))
+    } else {
+        None
+    }
+}
+
+/// CLI metadata of a .NET assembly, as described in ECMA-335 partition II
+pub struct Metadata<'a> {
+    strings : &'a [u8],
+    blobs : &'a [u8],
+    tables_data : &'a [u8],
+    tables : Vec<TableInfo>
+}
+
+impl<'a> Metadata<'a> {
+    pub fn parse(pe_file : &PeFile<'a>) -> Result<Self, String> {
+        let cli_header = pe_file.get_directory_data(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR)
+            .ok_or("It is a native dll and not a .NET assembly")?;
+        let metadata_rva = read_u32(cli_header, 8).ok_or("The CLI header is truncated")?;
+        let metadata_size = read_u32(cli_header, 12).ok_or("The CLI header is truncated")? as usize;
+        let metadata_offset = pe_file.rva_to_offset(metadata_rva).ok_or("The CLI metadata is outside of the file")?;
+        let metadata = pe_file.data.get(metadata_offset..metadata_offset + metadata_size).ok_or("The CLI metadata is truncated")?;
+        if read_u32(metadata, 0) != Some(METADATA_SIGNATURE) {
+            return Err("The CLI metadata has an invalid signature".to_string());
+        }
+        // Skip the version string, which is padded to 4 bytes
+        let version_length = read_u32(metadata, 12).ok_or("The CLI metadata is truncated")? as usize;
+        let stream_count_offset = 16 + version_length + 2;
+        let stream_count = read_u16(metadata, stream_count_offset).ok_or("The CLI metadata is truncated")?;
+        let mut offset = stream_count_offset + 2;
+        let (mut strings, mut blobs, mut tables_data) = (&[][..], &[][..], None);
+        for _ in 0..stream_count {
+            let stream_offset = read_u32(metadata, offset).ok_or("Truncated stream header")? as usize;
+            let stream_size = read_u32(metadata, offset + 4).ok_or("Truncated stream header")? as usize;
+            let name_bytes = metadata.get(offset + 8..).ok_or("Truncated stream header")?;
+            let name_length = name_bytes.iter().position(|b| *b == 0).ok_or("Truncated stream header")?;
+            let stream = metadata.get(stream_offset..stream_offset + stream_size).ok_or("A metadata stream is outside of the metadata")?;
+            match &name_bytes[..name_length] {
+                b"#Strings" => strings = stream,
+                b"#Blob" => blobs = stream,
+                b"#~" | b"#-" => tables_data = Some(stream),
+                _ => {}
+            }
+            offset += 8 + (name_length + 4) / 4 * 4;
+        }
+        let tables_data = tables_data.ok_or("The metadata tables are missing")?;
+        let mut metadata = Self { strings, blobs, tables_data, tables: vec![TableInfo::default(); TABLE_COUNT] };
+        metadata.read_table_layout()?;
+        Ok(metadata)
+    }
+
+    fn read_table_layout(&mut self) -> Result<(), String> {
+        let data = self.tables_data;
+        let heap_sizes = *data.get(6).ok_or("The metadata tables are truncated")?;
+        let valid = data.get(8..16).map(|b| u64::from_le_bytes(b.try_into().unwrap())).ok_or("The metadata tables are truncated")?;
+        let mut offset = 24;
+        for table in 0..TABLE_COUNT {
+            if valid & (1 << table) == 0 { continue; }
+            self.tables[table].rows = read_u32(data, offset).ok_or("The metadata tables are truncated")?;
+            offset += 4;
+        }
+        // Some obfuscators and the uncompressed table format add extra data after the row counts
+        if heap_sizes & 0x40 != 0 { offset += 4; }
+        let string_size = if heap_sizes & 0x01 != 0 { 4 } else { 2 };
+        let guid_size = if heap_sizes & 0x02 != 0 { 4 } else { 2 };
+        let blob_size = if heap_sizes & 0x04 != 0 { 4 } else { 2 };
+        for table in 0..TABLE_COUNT {
+            if self.tables[table].rows == 0 { continue; }
+            // Tables are stored in order, so the size of a table that comes after the known ones doesn't matter
+            let Some(schema) = get_table_schema(table) else { break; };
+            let column_sizes : Vec<usize> = schema.iter().map(|column| match column {
+                Column::U16 => 2,
+                Column::U32 => 4,
+                Column::Strings => string_size,
+                Column::Guid => guid_size,
+                Column::Blob => blob_size,
+                Column::Table(t) => if self.tables[*t].rows < (1 << 16) { 2 } else { 4 },
+                Column::Coded(coded_index) => {
+                    let (tag_bits, tables) = coded_index.tables();
+                    let max_rows = tables.iter().flatten().map(|t| self.tables[*t].rows).max().unwrap_or(0);
+                    if max_rows < (1 << (16 - tag_bits)) { 2 } else { 4 }
+                }
+            }).collect();
+            let info = &mut self.tables[table];
+            info.column_offsets = column_sizes.iter().scan(0, |o, size| { let current = *o; *o += size; Some(current) }).collect();
+            info.row_size = column_sizes.iter().sum();
+            info.column_sizes = column_sizes;
+            info.offset = offset;
+            offset += info.row_size * info.rows as usize;
+        }
+        if offset > data.len() {
+            return Err("The metadata tables are truncated".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn row_count(&self, table : usize) -> u32 {
+        self.tables[table].rows
+    }
+
+    /// Reads a raw column value from a row. Rows are indexed starting at 1, like in the metadata itself.
+    pub fn get(&self, table : usize, row : u32, column : usize) -> Option<u32> {
+        let info = &self.tables[table];
+        if row == 0 || row > info.rows { return None; }
+        let offset = info.offset + (row - 1) as usize * info.row_size + info.column_offsets.get(column)?;
+        match info.column_sizes[column] {
+            2 => read_u16(self.tables_data, offset).map(|v| v as u32),
+            _ => read_u32(self.tables_data, offset)
+        }
+    }
+
+    pub fn get_string(&self, table : usize, row : u32, column : usize) -> Option<String> {
+        let offset = self.get(table, row, column)? as usize;
+        let bytes = self.strings.get(offset..)?;
+        let length = bytes.iter().position(|b| *b == 0)?;
+        Some(String::from_utf8_lossy(&bytes[..length]).to_string())
+    }
+
+    pub fn get_blob(&self, table : usize, row : u32, column : usize) -> Option<&'a [u8]> {
+        let offset = self.get(table, row, column)? as usize;
+        let (length, length_size) = read_compressed_u32(self.blobs, offset)?;
+        self.blobs.get(offset + length_size..offset + length_size + length as usize)
+    }
+
+    /// Splits a coded index into the table and row it refers to
+    fn get_coded(&self, table : usize, row : u32, column : usize, coded_index : CodedIndex) -> Option<(usize, u32)> {
+        let value = self.get(table, row, column)?;
+        let (tag_bits, tables) = coded_index.tables();
+        let target_table = (*tables.get((value & ((1 << tag_bits) - 1)) as usize)?)?;
+        Some((target_table, value >> tag_bits))
+    }
+
+    /// Returns the namespace and name of the type the constructor of a custom attribute belongs to.
+    /// Only attributes defined in other assemblies are resolved, which is the case for all attributes from BepInEx.
+    fn get_attribute_type(&self, attribute_row : u32) -> Option<(String, String)> {
+        let (table, member_ref) = self.get_coded(TABLE_CUSTOM_ATTRIBUTE, attribute_row, 1, CodedIndex::CustomAttributeType)?;
+        if table != TABLE_MEMBER_REF { return None; }
+        let (table, type_ref) = self.get_coded(TABLE_MEMBER_REF, member_ref, 0, CodedIndex::MemberRefParent)?;
+        if table != TABLE_TYPE_REF { return None; }
+        Some((self.get_string(TABLE_TYPE_REF, type_ref, 2)?, self.get_string(TABLE_TYPE_REF, type_ref, 1)?))
+    }
+}
+
+/// Reads a string argument of a custom attribute. Returns `None` as the string for null.
+fn read_ser_string(data : &[u8], offset : usize) -> Option<(Option<String>, usize)> {
+    if *data.get(offset)? == 0xFF {
+        return Some((None, offset + 1));
+    }
+    let (length, length_size) = read_compressed_u32(data, offset)?;
+    let start = offset + length_size;
+    let bytes = data.get(start..start + length as usize)?;
+    Some((Some(String::from_utf8_lossy(bytes).to_string()), start + length as usize))
+}
+
+/// Values of a `[BepInPlugin(GUID, Name, Version)]` attribute
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginInfo {
+    pub guid : String,
+    pub name : String,
+    pub version : String
+}
+
+impl PluginInfo {
+    /// Finds all BepInEx plugins declared in an assembly
+    pub fn read_all(data : &[u8]) -> Result<Vec<Self>, String> {
+        let pe_file = PeFile::parse(data)?;
+        let metadata = Metadata::parse(&pe_file)?;
+        let mut plugins = Vec::new();
+        for row in 1..=metadata.row_count(TABLE_CUSTOM_ATTRIBUTE) {
+            let Some((namespace, name)) = metadata.get_attribute_type(row) else { continue; };
+            if namespace != "BepInEx" || name != "BepInPlugin" { continue; }
+            let Some(value) = metadata.get_blob(TABLE_CUSTOM_ATTRIBUTE, row, 2) else { continue; };
+            // Every attribute value starts with the prolog 0x0001, followed by the constructor arguments
+            if read_u16(value, 0) != Some(1) { continue; }
+            let mut offset = 2;
+            let mut arguments = Vec::with_capacity(3);
+            for _ in 0..3 {
+                let Some((argument, next_offset)) = read_ser_string(value, offset) else { break; };
+                arguments.push(argument.unwrap_or_default());
+                offset = next_offset;
+            }
+            if arguments.len() != 3 { continue; }
+            let mut arguments = arguments.into_iter();
+            plugins.push(Self {
+                guid: arguments.next().unwrap(),
+                name: arguments.next().unwrap(),
+                version: arguments.next().unwrap()
+            });
+        }
+        Ok(plugins)
+    }
+
+    pub fn read_file(path : &std::path::Path) -> Result<Vec<Self>, String> {
+        let data = std::fs::read(path).or(Err(format!("Cannot read {}", path.display())))?;
+        Self::read_all(&data)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssemblyReference {
+    pub name : String,
+    pub version : String
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssemblyInfo {
+    pub name : String,
+    pub version : String,
+    pub references : Vec<AssemblyReference>
+}
+
+fn get_version(metadata : &Metadata, table : usize, row : u32, first_column : usize) -> String {
+    (first_column..first_column + 4)
+        .map(|c| metadata.get(table, row, c).unwrap_or(0).to_string())
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+impl AssemblyInfo {
+    /// Reads the name, version and references of a .NET assembly.
+    /// Fails with a description of the problem, if the data is not a valid managed assembly.
+    pub fn read(data : &[u8]) -> Result<Self, String> {
+        let pe_file = PeFile::parse(data)?;
+        let metadata = Metadata::parse(&pe_file)?;
+        if metadata.row_count(TABLE_ASSEMBLY) == 0 {
+            return Err("It is a .NET module without an assembly manifest".to_string());
+        }
+        let name = metadata.get_string(TABLE_ASSEMBLY, 1, 7).ok_or("The assembly name is invalid")?;
+        let version = get_version(&metadata, TABLE_ASSEMBLY, 1, 1);
+        let references = (1..=metadata.row_count(TABLE_ASSEMBLY_REF)).map(|row| AssemblyReference {
+            name: metadata.get_string(TABLE_ASSEMBLY_REF, row, 6).unwrap_or_default(),
+            version: get_version(&metadata, TABLE_ASSEMBLY_REF, row, 0)
+        }).collect();
+        Ok(Self { name, version, references })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::tests::read_fixture;
+
+    #[test]
+    fn reads_the_bepinex_plugin_of_an_assembly() {
+        let plugins = PluginInfo::read_all(&read_fixture("managed.dll")).unwrap();
+        // The TargetFramework attribute of the assembly is skipped
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].guid, "com.example.plugin");
+        assert_eq!(plugins[0].name, "Example Plugin");
+        assert_eq!(plugins[0].version, "1.2.3");
+    }
+
+    #[test]
+    fn reads_the_name_version_and_references_of_an_assembly() {
+        let assembly_info = AssemblyInfo::read(&read_fixture("managed.dll")).unwrap();
+        let references : Vec<(&str, &str)> = assembly_info.references.iter().map(|r| (r.name.as_str(), r.version.as_str())).collect();
+        assert_eq!(assembly_info.name, "ExamplePlugin");
+        assert_eq!(assembly_info.version, "1.2.3.0");
+        assert_eq!(references, vec![("BepInEx.Core", "6.0.0.0"), ("netstandard", "2.1.0.0")]);
+    }
+
+    #[test]
+    fn rejects_native_and_truncated_dlls() {
+        let managed = read_fixture("managed.dll");
+        let metadata_start = managed.windows(4).position(|w| w == b"BSJB").unwrap();
+        assert_eq!(AssemblyInfo::read(&read_fixture("native.dll")).err().unwrap(), "It is a native dll and not a .NET assembly");
+        assert_eq!(PluginInfo::read_all(&managed[..metadata_start + 0x40]).err().unwrap(), "The CLI metadata is truncated");
+        assert!(AssemblyInfo::read(&managed[..0x100]).is_err());
+    }
+
+    #[test]
+    fn reads_compressed_integers() {
+        assert_eq!(read_compressed_u32(&[0x03], 0), Some((0x03, 1)));
+        assert_eq!(read_compressed_u32(&[0x80, 0x80], 0), Some((0x80, 2)));
+        assert_eq!(read_compressed_u32(&[0xC0, 0x00, 0x40, 0x00], 0), Some((0x4000, 4)));
+        assert_eq!(read_compressed_u32(&[0xC0, 0x00], 0), None);
+        assert_eq!(read_compressed_u32(&[0xFF], 0), None);
+    }
+}
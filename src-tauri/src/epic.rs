@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+
+/// The parts of an Epic Games Launcher `.item` manifest that are needed to find and launch a game
+#[derive(Debug, Clone, Deserialize)]
+struct EpicManifest {
+    #[serde(rename = "DisplayName", default)]
+    display_name : String,
+    #[serde(rename = "InstallLocation")]
+    install_location : String,
+    #[serde(rename = "LaunchExecutable", default)]
+    launch_executable : String,
+    #[serde(rename = "AppName")]
+    app_name : String,
+    #[serde(rename = "CatalogNamespace")]
+    catalog_namespace : String,
+    #[serde(rename = "CatalogItemId")]
+    catalog_item_id : String,
+    #[serde(rename = "AppVersionString", default)]
+    app_version : String,
+    #[serde(rename = "bIsIncompleteInstall", default)]
+    is_incomplete_install : bool
+}
+
+/// Where Among Us is installed through the Epic Games Launcher and the ids needed to launch it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpicInstall {
+    pub install_path : PathBuf,
+    pub app_name : String,
+    pub catalog_namespace : String,
+    pub catalog_item_id : String,
+    pub app_version : String
+}
+
+impl EpicInstall {
+    /// Launching through the launcher is required, because the game refuses to start without an Epic login
+    pub fn launch_uri(&self) -> String {
+        format!(
+            "com.epicgames.launcher://apps/{}%3A{}%3A{}?action=launch&silent=true",
+            self.catalog_namespace,
+            self.catalog_item_id,
+            self.app_name
+        )
+    }
+}
+
+#[cfg(target_family = "windows")]
+fn get_manifests_path() -> Option<PathBuf> {
+    let program_data = std::env::var_os("PROGRAMDATA").map(PathBuf::from).unwrap_or(PathBuf::from("C:\\ProgramData"));
+    Some(program_data.join("Epic").join("EpicGamesLauncher").join("Data").join("Manifests"))
+}
+
+#[cfg(target_family = "unix")]
+fn get_manifests_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from)?;
+    Some(home.join("Library/Application Support/Epic/EpicGamesLauncher/Data/Manifests"))
+}
+
+fn read_manifest(path : &Path) -> Result<EpicManifest, String> {
+    let text = std::fs::read_to_string(path).or(Err(format!("Cannot read {}", path.display())))?;
+    serde_json::from_str(&text).map_err(|e| format!("Cannot parse {}: {}", path.display(), e))
+}
+
+pub fn find_among_us() -> Option<EpicInstall> {
+    let read_dir = get_manifests_path()?.read_dir().ok()?;
+    for entry in read_dir.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("item") { continue; }
+        let manifest = match read_manifest(&path) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                log_error!("{}", e);
+                continue;
+            }
+        };
+        if manifest.is_incomplete_install { continue; }
+        // The executable name is more reliable than the display name, which can be localized
+        let executable_name = Path::new(&manifest.launch_executable).file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if !executable_name.eq_ignore_ascii_case("Among Us.exe") && manifest.display_name != "Among Us" { continue; }
+        let install_path = PathBuf::from(&manifest.install_location);
+        if !install_path.join("Among Us.exe").is_file() { continue; }
+        return Some(EpicInstall {
+            install_path,
+            app_name: manifest.app_name,
+            catalog_namespace: manifest.catalog_namespace,
+            catalog_item_id: manifest.catalog_item_id,
+            app_version: manifest.app_version
+        });
+    }
+    None
+}
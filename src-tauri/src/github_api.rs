@@ -3,6 +3,7 @@ use reqwest::Client;
 use serde_json::Value;
 use once_cell::sync::Lazy;
 use tokio::sync::Mutex;
+use crate::sniff;
 
 static RESPONSE_CACHE : Lazy<Mutex<HashMap<String, (String, Value)>>> = Lazy::new(|| Default::default());
 
@@ -47,14 +48,8 @@ pub async fn make_github_api_request(client : &reqwest::Client, route : String)
 
 pub fn get_assets(newest_release: &Value) -> Vec<Value> {
     let mut assets = newest_release.get("assets").unwrap().as_array().unwrap().clone();
-    // Only keep assets that are .dll or .zip files
-    assets.retain(|v| match v.get("content_type").unwrap().as_str().unwrap() {
-        "application/x-msdownload" => true,
-        "application/x-zip-compressed" => true,
-        "application/zip" => true,
-        "application/octet-stream" => true,
-        _ => false
-    });
+    // Only keep assets that look like .dll files or archives or that could be one of them
+    assets.retain(sniff::is_candidate_asset);
     // Sort by download count
     assets.sort_by(|a, b| {
         let a_download_count = a.get("download_count").unwrap().as_u64().unwrap();
@@ -74,3 +69,7 @@ pub async fn get_newest_release(client: &Client, username: &String, repository_n
     };
     Ok(newest_release.clone())
 }
+
+pub async fn get_release_by_tag(client: &Client, username: &String, repository_name: &String, tag: &String) -> Result<Value, String> {
+    make_github_api_request(&client, format!("repos/{}/{}/releases/tags/{}", username, repository_name, tag)).await
+}
@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+
+/// Where Among Us is installed through the itch.io app
+#[derive(Debug, Clone, Serialize)]
+pub struct ItchInstall {
+    pub install_path : PathBuf,
+    pub cave_id : String
+}
+
+#[cfg(target_family = "windows")]
+fn get_database_candidates() -> Vec<PathBuf> {
+    let Some(app_data) = std::env::var_os("APPDATA").map(PathBuf::from) else { return vec![]; };
+    vec![app_data.join("itch").join("db").join("butler.db")]
+}
+
+#[cfg(target_family = "unix")]
+fn get_database_candidates() -> Vec<PathBuf> {
+    let Some(home) = std::env::var_os("HOME").map(PathBuf::from) else { return vec![]; };
+    vec![
+        home.join(".config/itch/db/butler.db"),
+        // macOS
+        home.join("Library/Application Support/itch/db/butler.db")
+    ]
+}
+
+/// Looks up installed games (called caves by itch) named Among Us in the database of itch's butler daemon
+fn read_caves(database_path : &Path) -> Result<Vec<ItchInstall>, String> {
+    // Read only, because the itch app might have the database open
+    let connection = Connection::open_with_flags(database_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Cannot open {}: {}", database_path.display(), e))?;
+    let mut statement = connection.prepare(
+        "SELECT caves.id, install_locations.path, caves.install_folder_name FROM caves \
+         JOIN games ON games.id = caves.game_id \
+         JOIN install_locations ON install_locations.id = caves.install_location_id \
+         WHERE games.title LIKE 'Among Us'"
+    ).map_err(|e| format!("Cannot query {}: {}", database_path.display(), e))?;
+    let rows = statement.query_map([], |row| Ok(ItchInstall {
+        cave_id: row.get(0)?,
+        install_path: PathBuf::from(row.get::<_, String>(1)?).join(row.get::<_, String>(2)?)
+    })).map_err(|e| format!("Cannot query {}: {}", database_path.display(), e))?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+pub fn find_among_us() -> Option<ItchInstall> {
+    for database_path in get_database_candidates() {
+        if !database_path.is_file() { continue; }
+        match read_caves(&database_path) {
+            Ok(caves) => {
+                if let Some(install) = caves.into_iter().find(|c| c.install_path.join("Among Us.exe").is_file()) {
+                    return Some(install);
+                }
+            },
+            Err(e) => log_error!("{}", e)
+        }
+    }
+    None
+}
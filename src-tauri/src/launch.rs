@@ -0,0 +1,544 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use once_cell::sync::Lazy;
+use serde::{Serialize, Deserialize};
+use tauri::Window;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Child;
+use tokio::sync::Mutex;
+use crate::config::Storefront;
+use crate::{epic, steam, sync, wine, AMONG_US_STEAM_ID};
+
+const MAX_GAME_OUTPUT_LINES : usize = 2000;
+
+/// The last lines the game printed, when it was started as a child process
+static GAME_OUTPUT : Lazy<Mutex<VecDeque<String>>> = Lazy::new(|| Default::default());
+
+/// How the game gets started
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum LaunchStrategy {
+    /// Opens `steam://` so Steam starts the game, which is needed for the Steam overlay and playtime tracking
+    SteamUri,
+    /// Opens `com.epicgames.launcher://`, because Epic builds need a login from the launcher.
+    /// The launcher has no way to pass arguments to the game, only its own settings can do that.
+    EpicUri,
+    /// Starts `Among Us.exe` directly
+    Executable,
+    /// Starts `Among Us.exe` through Wine. Paths that are not set are detected.
+    Wine {
+        wine_path : Option<String>,
+        prefix : Option<String>
+    },
+    /// Starts `Among Us.exe` through a Proton install outside of Steam. Paths that are not set are detected.
+    Proton {
+        proton_path : Option<String>,
+        compat_data_path : Option<String>
+    },
+    /// A user defined command line. `{exe}`, `{game_dir}` and `{args}` are replaced before it is run.
+    Custom {
+        template : String
+    }
+}
+
+/// Which launch options a strategy can pass on to the game, so the UI can disable the others
+#[derive(Debug, Clone, Serialize)]
+pub struct LaunchSupport {
+    pub args : bool,
+    pub env : bool,
+    /// Limitations the user should know about
+    pub note : Option<String>
+}
+
+impl LaunchStrategy {
+    pub fn get_support(&self) -> LaunchSupport {
+        // The variables are set for the process opening the URI, a launcher that is already running never sees them
+        let env_note = |launcher : &str| format!("Environment variables only reach the game if {} is not running yet", launcher);
+        match self {
+            LaunchStrategy::SteamUri => LaunchSupport { args: true, env: true, note: Some(env_note("Steam")) },
+            LaunchStrategy::EpicUri => LaunchSupport {
+                args: false,
+                env: true,
+                note: Some(format!("{}. Arguments have to be set in the Epic Games Launcher", env_note("the Epic Games Launcher")))
+            },
+            _ => LaunchSupport { args: true, env: true, note: None }
+        }
+    }
+
+    #[cfg(target_family = "windows")]
+    pub fn for_storefront(storefront : Storefront) -> Self {
+        match storefront {
+            Storefront::Steam => LaunchStrategy::SteamUri,
+            Storefront::Epic => LaunchStrategy::EpicUri,
+            Storefront::Itch | Storefront::Unknown => LaunchStrategy::Executable
+        }
+    }
+
+    /// Steam can't be told to set the dll override for BepInEx, so the game is started through Proton directly
+    #[cfg(target_family = "unix")]
+    pub fn for_storefront(storefront : Storefront) -> Self {
+        match storefront {
+            Storefront::Steam => LaunchStrategy::Proton { proton_path: None, compat_data_path: None },
+            Storefront::Epic => LaunchStrategy::EpicUri,
+            Storefront::Itch | Storefront::Unknown => LaunchStrategy::Wine { wine_path: None, prefix: None }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaunchOptions {
+    pub strategy : LaunchStrategy,
+    /// Extra arguments for the game
+    #[serde(default)]
+    pub args : Vec<String>,
+    /// Extra environment variables for the game
+    #[serde(default)]
+    pub env : BTreeMap<String, String>,
+    /// Restore the vanilla game from the backup once it has exited
+    #[serde(default)]
+    pub temporary_session : bool
+}
+
+/// What actually gets run to start the game
+#[derive(Debug, Clone, PartialEq)]
+pub enum LaunchCommand {
+    /// Opened by the default handler of the URI, which gets the environment variables
+    Uri {
+        uri : String,
+        env : BTreeMap<String, String>
+    },
+    Process {
+        program : String,
+        args : Vec<String>,
+        env : BTreeMap<String, String>,
+        working_dir : PathBuf
+    }
+}
+
+/// Quotes an argument the way a shell would need it, so the preview can be copied into a terminal
+fn quote_argument(argument : &str) -> String {
+    if !argument.is_empty() && !argument.chars().any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | '$' | '`')) {
+        return argument.to_string();
+    }
+    format!("\"{}\"", argument.replace('\\', "\\\\").replace('"', "\\\"").replace('$', "\\$").replace('`', "\\`"))
+}
+
+/// Splits a command line template into arguments, following the rules of the platform so paths can be pasted as they are
+fn split_command_line(command_line : &str) -> Result<Vec<String>, String> {
+    if cfg!(target_family = "windows") { split_windows_command_line(command_line) } else { split_unix_command_line(command_line) }
+}
+
+/// Double quotes group words. Backslashes are only special in front of a double quote, like `CommandLineToArgvW` handles them:
+/// `2n` backslashes become `n` before a quote that groups words, `2n + 1` become `n` followed by a literal quote.
+fn split_windows_command_line(command_line : &str) -> Result<Vec<String>, String> {
+    let mut arguments = Vec::new();
+    let mut current : Option<String> = None;
+    let mut in_quotes = false;
+    let mut chars = command_line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let mut backslashes = 1;
+                while chars.next_if_eq(&'\\').is_some() {
+                    backslashes += 1;
+                }
+                let argument = current.get_or_insert_with(String::new);
+                if chars.peek() != Some(&'"') {
+                    argument.push_str(&"\\".repeat(backslashes));
+                    continue;
+                }
+                argument.push_str(&"\\".repeat(backslashes / 2));
+                if backslashes % 2 == 1 {
+                    chars.next();
+                    argument.push('"');
+                }
+            },
+            '"' => {
+                in_quotes = !in_quotes;
+                current.get_or_insert_with(String::new);
+            },
+            c if c.is_whitespace() && !in_quotes => {
+                if let Some(argument) = current.take() {
+                    arguments.push(argument);
+                }
+            },
+            c => current.get_or_insert_with(String::new).push(c)
+        }
+    }
+    if in_quotes {
+        return Err("The command line has an unterminated quote".to_string());
+    }
+    arguments.extend(current);
+    Ok(arguments)
+}
+
+/// Double and single quotes group words. Inside double quotes a backslash escapes `"`, `\`, `$` and `` ` ``, like in a shell.
+/// Everywhere else backslashes are kept, so Windows paths for Wine don't need to be escaped.
+fn split_unix_command_line(command_line : &str) -> Result<Vec<String>, String> {
+    let mut arguments = Vec::new();
+    let mut current : Option<String> = None;
+    let mut quote = None;
+    let mut chars = command_line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('"')) => {
+                let escaped = chars.next_if(|c| matches!(c, '"' | '\\' | '$' | '`'));
+                current.get_or_insert_with(String::new).push(escaped.unwrap_or('\\'));
+            },
+            (q, None) if q == '"' || q == '\'' => {
+                quote = Some(q);
+                current.get_or_insert_with(String::new);
+            },
+            (c, Some(q)) if c == q => quote = None,
+            (c, None) if c.is_whitespace() => {
+                if let Some(argument) = current.take() {
+                    arguments.push(argument);
+                }
+            },
+            (c, _) => current.get_or_insert_with(String::new).push(c)
+        }
+    }
+    if quote.is_some() {
+        return Err("The command line has an unterminated quote".to_string());
+    }
+    arguments.extend(current);
+    Ok(arguments)
+}
+
+/// Steam passes everything after `//` in a `steam://run` URI to the game as its command line
+fn encode_uri_component(component : &str) -> String {
+    component.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        b => format!("%{:02X}", b)
+    }).collect()
+}
+
+impl LaunchOptions {
+    pub fn new(strategy : LaunchStrategy) -> Self {
+        Self { strategy, args: vec![], env: BTreeMap::new(), temporary_session: false }
+    }
+
+    /// Rejects options the strategy can't pass on, so they are caught when they are saved instead of when the game starts
+    pub fn validate(&self) -> Result<(), String> {
+        let support = self.strategy.get_support();
+        if !support.args && !self.args.is_empty() {
+            return Err(support.note.unwrap_or("Arguments cannot be passed to the game with this launch strategy".to_string()));
+        }
+        if !support.env && !self.env.is_empty() {
+            return Err(support.note.unwrap_or("Environment variables cannot be passed to the game with this launch strategy".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn build_command(&self, among_us_path : &Path) -> Result<LaunchCommand, String> {
+        let executable_path = among_us_path.join("Among Us.exe");
+        let executable = executable_path.display().to_string();
+        let process = |program : String, args : Vec<String>, env : BTreeMap<String, String>| LaunchCommand::Process {
+            program,
+            args,
+            env,
+            working_dir: among_us_path.to_path_buf()
+        };
+        match &self.strategy {
+            LaunchStrategy::SteamUri => {
+                if self.args.is_empty() {
+                    return Ok(LaunchCommand::Uri { uri: format!("steam://rungameid/{}", AMONG_US_STEAM_ID), env: self.env.clone() });
+                }
+                let command_line = self.args.iter().map(|a| quote_argument(a)).collect::<Vec<_>>().join(" ");
+                let uri = format!("steam://run/{}//{}/", AMONG_US_STEAM_ID, encode_uri_component(&command_line));
+                Ok(LaunchCommand::Uri { uri, env: self.env.clone() })
+            },
+            LaunchStrategy::EpicUri => {
+                // Options saved with another strategy are not rejected here, so switching the strategy can't break the launch
+                if !self.args.is_empty() {
+                    log_info!("Ignoring the arguments {:?}, the Epic Games Launcher cannot pass them to the game", self.args);
+                }
+                let epic_install = epic::find_among_us().ok_or("Cannot find Among Us in the Epic Games Launcher manifests")?;
+                Ok(LaunchCommand::Uri { uri: epic_install.launch_uri(), env: self.env.clone() })
+            },
+            LaunchStrategy::Executable => Ok(process(executable, self.args.clone(), self.env.clone())),
+            LaunchStrategy::Wine { wine_path, prefix } => {
+                let wine_path = match wine_path {
+                    Some(wine_path) => wine_path.clone(),
+                    None => wine::find_wine().ok_or("Cannot find Wine, please install it or set its path")?.display().to_string()
+                };
+                let mut env = self.env.clone();
+                let prefix = prefix.clone().or(wine::get_default_prefix().map(|p| p.display().to_string()));
+                if let Some(prefix) = prefix {
+                    env.insert("WINEPREFIX".to_string(), prefix);
+                }
+                wine::add_dll_override(&mut env);
+                let mut args = vec![executable];
+                args.extend(self.args.iter().cloned());
+                Ok(process(wine_path, args, env))
+            },
+            LaunchStrategy::Proton { proton_path, compat_data_path } => {
+                let proton_path = match proton_path {
+                    Some(proton_path) => proton_path.clone(),
+                    None => wine::find_proton().ok_or("Cannot find Proton, please install it through Steam or set its path")?.display().to_string()
+                };
+                let compat_data_path = match compat_data_path {
+                    Some(compat_data_path) => compat_data_path.clone(),
+                    None => wine::get_default_compat_data_path().ok_or("Cannot find the Proton compatibility data of Among Us, please set its path")?.display().to_string()
+                };
+                let mut env = self.env.clone();
+                env.insert("WINEPREFIX".to_string(), Path::new(&compat_data_path).join("pfx").display().to_string());
+                env.insert("STEAM_COMPAT_DATA_PATH".to_string(), compat_data_path);
+                if let Some(steam_root) = steam::find_steam_root() {
+                    env.insert("STEAM_COMPAT_CLIENT_INSTALL_PATH".to_string(), steam_root.display().to_string());
+                }
+                env.insert("SteamAppId".to_string(), AMONG_US_STEAM_ID.to_string());
+                wine::add_dll_override(&mut env);
+                let mut args = vec!["run".to_string(), executable];
+                args.extend(self.args.iter().cloned());
+                Ok(process(proton_path, args, env))
+            },
+            LaunchStrategy::Custom { template } => {
+                let mut command_line = Vec::new();
+                for argument in split_command_line(template)? {
+                    // {args} on its own expands to all arguments, so they don't get merged into one
+                    if argument == "{args}" {
+                        command_line.extend(self.args.iter().cloned());
+                        continue;
+                    }
+                    command_line.push(
+                        argument
+                            .replace("{exe}", &executable)
+                            .replace("{game_dir}", &among_us_path.display().to_string())
+                            .replace("{args}", &self.args.join(" "))
+                    );
+                }
+                if command_line.is_empty() {
+                    return Err("The launch command template is empty".to_string());
+                }
+                let program = command_line.remove(0);
+                Ok(process(program, command_line, self.env.clone()))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for LaunchCommand {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let write_env = |f : &mut std::fmt::Formatter<'_>, env : &BTreeMap<String, String>| -> std::fmt::Result {
+            for (key, value) in env {
+                write!(f, "{}={} ", key, quote_argument(value))?;
+            }
+            Ok(())
+        };
+        match self {
+            LaunchCommand::Uri { uri, env } => {
+                write_env(f, env)?;
+                write!(f, "{}", uri)
+            },
+            LaunchCommand::Process { program, args, env, .. } => {
+                write_env(f, env)?;
+                write!(f, "{}", quote_argument(program))?;
+                for argument in args {
+                    write!(f, " {}", quote_argument(argument))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Like `open::that`, but the handler of the URI is started with extra environment variables
+fn open_uri(uri : &str, env : &BTreeMap<String, String>) -> Result<(), String> {
+    let mut last_error = None;
+    // Every command is a different way of opening the URI, the first one that works is used
+    for mut command in open::commands(uri) {
+        let result = command.envs(env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match result {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => last_error = Some(format!("{:?} exited with {}", command.get_program(), status)),
+            Err(e) => last_error = Some(e.to_string())
+        }
+    }
+    Err(format!("Could not open {}: {}", uri, last_error.unwrap_or("there is no program to open it".to_string())))
+}
+
+/// Emits every line of a stream of the game as a `game_output` event and keeps it for `get_game_output`
+async fn forward_output<R : AsyncRead + Unpin>(stream : R, window : Window) {
+    let mut lines = BufReader::new(stream).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let mut game_output = GAME_OUTPUT.lock().await;
+        if game_output.len() >= MAX_GAME_OUTPUT_LINES {
+            game_output.pop_front();
+        }
+        game_output.push_back(line.clone());
+        drop(game_output);
+        window.emit("game_output", line).unwrap();
+    }
+}
+
+pub async fn get_game_output() -> Vec<String> {
+    GAME_OUTPUT.lock().await.iter().cloned().collect()
+}
+
+impl LaunchCommand {
+    /// Starts the game and returns its process, if it was started directly instead of through a launcher
+    pub async fn run(&self, window : &Window) -> Result<Option<Child>, String> {
+        match self {
+            LaunchCommand::Uri { uri, env } => {
+                let (uri, env) = (uri.clone(), env.clone());
+                sync::run_blocking(move || open_uri(&uri, &env)).await?;
+                Ok(None)
+            },
+            LaunchCommand::Process { program, args, env, working_dir } => {
+                let mut child = tokio::process::Command::new(program)
+                    .args(args)
+                    .envs(env)
+                    .current_dir(working_dir)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| format!("Could not start {}: {}", program, e))?;
+                GAME_OUTPUT.lock().await.clear();
+                if let Some(stdout) = child.stdout.take() {
+                    tokio::spawn(forward_output(stdout, window.clone()));
+                }
+                if let Some(stderr) = child.stderr.take() {
+                    tokio::spawn(forward_output(stderr, window.clone()));
+                }
+                Ok(Some(child))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_unix(command_line : &str) -> Vec<String> {
+        split_unix_command_line(command_line).unwrap()
+    }
+
+    fn split_windows(command_line : &str) -> Vec<String> {
+        split_windows_command_line(command_line).unwrap()
+    }
+
+    fn custom(template : &str, args : &[&str]) -> LaunchOptions {
+        LaunchOptions {
+            strategy: LaunchStrategy::Custom { template: template.to_string() },
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env: BTreeMap::new(),
+            temporary_session: false
+        }
+    }
+
+    fn get_program_and_args(command : LaunchCommand) -> (String, Vec<String>) {
+        match command {
+            LaunchCommand::Process { program, args, .. } => (program, args),
+            LaunchCommand::Uri { uri, .. } => panic!("Expected a process, got {}", uri)
+        }
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        for split in [split_unix, split_windows] {
+            assert_eq!(split("--foo  bar\t-baz\n"), vec!["--foo", "bar", "-baz"]);
+            assert!(split("   ").is_empty());
+            assert_eq!(split("--name \"Among Us\" \"\""), vec!["--name", "Among Us", ""]);
+            assert_eq!(split("--path=\"C:/Program Files\"/game"), vec!["--path=C:/Program Files/game"]);
+        }
+    }
+
+    #[test]
+    fn keeps_windows_paths_on_windows() {
+        assert_eq!(split_windows("C:\\Games\\Among Us\\x.exe {exe}"), vec!["C:\\Games\\Among", "Us\\x.exe", "{exe}"]);
+        assert_eq!(split_windows("\"C:\\Games\\Among Us\\x.exe\" {exe}"), vec!["C:\\Games\\Among Us\\x.exe", "{exe}"]);
+        assert_eq!(split_windows("\\\\server\\share\\ C:\\"), vec!["\\\\server\\share\\", "C:\\"]);
+        assert_eq!(split_windows("'single quotes' are text"), vec!["'single", "quotes'", "are", "text"]);
+    }
+
+    #[test]
+    fn handles_backslashes_before_quotes_on_windows() {
+        // A quoted folder ending in a backslash needs it doubled, like for any other Windows program
+        assert_eq!(split_windows("\"C:\\Among Us\\\\\" next"), vec!["C:\\Among Us\\", "next"]);
+        assert_eq!(split_windows("\"say \\\"hi\\\"\""), vec!["say \"hi\""]);
+        assert_eq!(split_windows("a\\\\\\\"b"), vec!["a\\\"b"]);
+        assert!(split_windows_command_line("\"unterminated").is_err());
+    }
+
+    #[test]
+    fn keeps_windows_paths_for_wine() {
+        assert_eq!(split_unix("wine C:\\Games\\x.exe"), vec!["wine", "C:\\Games\\x.exe"]);
+        assert_eq!(split_unix("wine \"C:\\Games\\Among Us\\x.exe\""), vec!["wine", "C:\\Games\\Among Us\\x.exe"]);
+        assert_eq!(split_unix("trailing\\"), vec!["trailing\\"]);
+    }
+
+    #[test]
+    fn handles_quotes_like_a_shell_on_unix() {
+        assert_eq!(split_unix("'two words' \"it's\" 'say \"hi\"'"), vec!["two words", "it's", "say \"hi\""]);
+        assert_eq!(split_unix("\"say \\\"hi\\\" for \\$5\""), vec!["say \"hi\" for $5"]);
+        assert_eq!(split_unix("'a\\\" b'"), vec!["a\\\" b"]);
+        assert!(split_unix_command_line("\"unterminated").is_err());
+        assert!(split_unix_command_line("'unterminated").is_err());
+    }
+
+    #[test]
+    fn quoted_arguments_split_back_into_themselves() {
+        let arguments = ["plain", "two words", "C:\\Games\\Among Us", "say \"hi\"", "$HOME", ""];
+        let command_line = arguments.iter().map(|a| quote_argument(a)).collect::<Vec<_>>().join(" ");
+        assert_eq!(split_unix(&command_line), arguments);
+    }
+
+    #[test]
+    fn builds_custom_commands() {
+        let among_us_path = Path::new("/games/Among Us");
+        let executable = among_us_path.join("Among Us.exe").display().to_string();
+        let options = custom("launcher --exe {exe} --dir={game_dir} {args} --last", &["--one", "two words"]);
+        let (program, args) = get_program_and_args(options.build_command(among_us_path).unwrap());
+        assert_eq!(program, "launcher");
+        assert_eq!(args, vec![
+            "--exe".to_string(),
+            executable,
+            "--dir=/games/Among Us".to_string(),
+            "--one".to_string(),
+            "two words".to_string(),
+            "--last".to_string()
+        ]);
+        assert!(custom("   ", &[]).build_command(among_us_path).is_err());
+    }
+
+    #[cfg(target_family = "windows")]
+    #[test]
+    fn builds_custom_commands_with_windows_paths() {
+        let options = custom("C:\\Tools\\wrapper.exe {exe}", &[]);
+        let (program, args) = get_program_and_args(options.build_command(Path::new("C:\\Games\\Among Us")).unwrap());
+        assert_eq!(program, "C:\\Tools\\wrapper.exe");
+        assert_eq!(args, vec!["C:\\Games\\Among Us\\Among Us.exe"]);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn builds_custom_commands_with_windows_paths() {
+        let options = custom("wine C:\\Tools\\wrapper.exe {exe}", &[]);
+        let (program, args) = get_program_and_args(options.build_command(Path::new("/games/Among Us")).unwrap());
+        assert_eq!(program, "wine");
+        assert_eq!(args, vec!["C:\\Tools\\wrapper.exe", "/games/Among Us/Among Us.exe"]);
+    }
+
+    #[test]
+    fn builds_executable_and_steam_commands() {
+        let among_us_path = Path::new("/games/Among Us");
+        let mut options = custom("", &["--one"]);
+        options.strategy = LaunchStrategy::Executable;
+        let (program, args) = get_program_and_args(options.build_command(among_us_path).unwrap());
+        assert_eq!(program, among_us_path.join("Among Us.exe").display().to_string());
+        assert_eq!(args, vec!["--one"]);
+        options.strategy = LaunchStrategy::SteamUri;
+        match options.build_command(among_us_path).unwrap() {
+            LaunchCommand::Uri { uri, .. } => assert_eq!(uri, format!("steam://run/{}//--one/", AMONG_US_STEAM_ID)),
+            command => panic!("Expected a URI, got {}", command)
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use once_cell::sync::Lazy;
+
+pub const LOG_PATH : &'static str = "sussy_launcher.log";
+/// The log of the previous run is kept, because that's usually the one with the problem
+pub const PREVIOUS_LOG_PATH : &'static str = "sussy_launcher.previous.log";
+
+static LOG_FILE : Lazy<Mutex<Option<File>>> = Lazy::new(|| Default::default());
+
+/// Like `println!`, but also writes the line into the launcher log
+macro_rules! log_info {
+    ($($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        println!("{}", message);
+        crate::launcher_log::write("INFO", &message);
+    }}
+}
+
+/// Like `eprintln!`, but also writes the line into the launcher log
+macro_rules! log_error {
+    ($($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        eprintln!("{}", message);
+        crate::launcher_log::write("ERROR", &message);
+    }}
+}
+
+pub fn init() {
+    let log_path = Path::new(LOG_PATH);
+    if log_path.exists() {
+        let _ = std::fs::rename(log_path, PREVIOUS_LOG_PATH);
+    }
+    match File::create(log_path) {
+        Ok(file) => *LOG_FILE.lock().unwrap() = Some(file),
+        Err(e) => eprintln!("Cannot create launcher log: {}", e)
+    }
+}
+
+pub fn write(level : &str, message : &str) {
+    let mut log_file = LOG_FILE.lock().unwrap();
+    let Some(file) = log_file.as_mut() else { return; };
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let _ = writeln!(file, "{} [{}] {}", timestamp, level, message);
+}
@@ -0,0 +1,321 @@
+use std::collections::VecDeque;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tauri::Window;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use crate::config::Config;
+
+const POLL_INTERVAL_MS : u64 = 500;
+const MAX_LOG_ENTRIES : usize = 5000;
+/// Shorter names would match too much unrelated text in stack traces
+const MIN_NEEDLE_LENGTH : usize = 4;
+
+/// Entries of the current or last session
+static LOG_ENTRIES : Lazy<Mutex<VecDeque<LogEntry>>> = Lazy::new(|| Default::default());
+/// The mod that caused the last error, used to blame a crash on it
+static LAST_ERROR_MOD : Lazy<Mutex<Option<String>>> = Lazy::new(|| Default::default());
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum LogFile {
+    /// `BepInEx/LogOutput.log`
+    BepInEx,
+    /// Unity's `Player.log`
+    Player
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    pub file : LogFile,
+    pub level : String,
+    pub source : String,
+    /// Can span multiple lines, for example when it contains a stack trace
+    pub message : String,
+    /// The mod this entry is attributed to, if it is an error that could be traced back to one
+    pub mod_name : Option<String>
+}
+
+impl LogEntry {
+    pub fn is_error(&self) -> bool {
+        matches!(self.level.as_str(), "Error" | "Fatal") || self.message.contains("Exception")
+    }
+}
+
+/// What identifies a mod in log messages
+struct ModSignature {
+    name : String,
+    /// BepInEx uses the plugin name as the source of log entries
+    plugin_names : Vec<String>,
+    /// GUIDs and assembly names, which show up in stack traces
+    needles : Vec<String>
+}
+
+fn get_mod_signatures(config : &Config) -> Vec<ModSignature> {
+    config.downloaded.iter().filter(|m| m.enabled).map(|modification| {
+        let plugins = modification.read_plugin_infos(config);
+        let mut needles : Vec<String> = modification.plugin_guids.clone();
+        needles.extend(modification.get_plugin_dll_paths(config).iter()
+            .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().to_string())));
+        needles.retain(|n| n.len() >= MIN_NEEDLE_LENGTH);
+        ModSignature {
+            name: modification.name.clone(),
+            plugin_names: plugins.into_iter().map(|p| p.name).collect(),
+            needles
+        }
+    }).collect()
+}
+
+fn attribute_entry(entry : &LogEntry, signatures : &[ModSignature]) -> Option<String> {
+    if !entry.is_error() { return None; }
+    signatures.iter()
+        .find(|s| s.plugin_names.iter().any(|n| n == &entry.source))
+        .or_else(|| signatures.iter().find(|s| s.needles.iter().any(|n| entry.message.contains(n.as_str()))))
+        .map(|s| s.name.clone())
+}
+
+/// Parses the header of a BepInEx log line, which looks like `[Error  :TheOtherRoles] message`
+fn parse_bepinex_header(line : &str) -> Option<(String, String, String)> {
+    let rest = line.strip_prefix('[')?;
+    let (header, message) = rest.split_once(']')?;
+    let (level, source) = header.split_once(':')?;
+    Some((level.trim().to_string(), source.trim().to_string(), message.trim_start().to_string()))
+}
+
+/// Returns a new entry if the line starts one, otherwise the line belongs to the previous entry
+fn parse_line(file : LogFile, line : &str) -> Option<LogEntry> {
+    match file {
+        LogFile::BepInEx => parse_bepinex_header(line).map(|(level, source, message)| LogEntry {
+            file, level, source, message, mod_name: None
+        }),
+        LogFile::Player => {
+            // Stack traces are indented, Unity adds `(Filename: ...)` after them
+            if line.is_empty() || line.starts_with(char::is_whitespace) || line.starts_with("(Filename:") { return None; }
+            let level = if line.contains("Exception") || line.contains("Error") { "Error" } else { "Info" };
+            Some(LogEntry { file, level: level.to_string(), source: "Unity".to_string(), message: line.to_string(), mod_name: None })
+        }
+    }
+}
+
+#[cfg(target_family = "windows")]
+fn get_player_log_path(_config : &Config) -> Option<PathBuf> {
+    let user_profile = std::env::var_os("USERPROFILE").map(PathBuf::from)?;
+    Some(user_profile.join("AppData/LocalLow/Innersloth/Among Us/Player.log"))
+}
+
+/// Under Wine the log is in the user folder of the prefix
+#[cfg(target_family = "unix")]
+fn get_player_log_path(config : &Config) -> Option<PathBuf> {
+    use crate::launch::LaunchStrategy;
+    let prefix = match &config.launch.strategy {
+        LaunchStrategy::Wine { prefix: Some(prefix), .. } => PathBuf::from(prefix),
+        LaunchStrategy::Proton { compat_data_path: Some(compat_data_path), .. } => Path::new(compat_data_path).join("pfx"),
+        _ => crate::wine::get_default_prefix()?
+    };
+    let users_path = prefix.join("drive_c/users");
+    let log_path = |user : &Path| user.join("AppData/LocalLow/Innersloth/Among Us/Player.log");
+    users_path.read_dir().ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| log_path(&e.path()))
+        .find(|p| p.is_file())
+        .or(Some(log_path(&users_path.join("steamuser"))))
+}
+
+pub fn get_log_paths(config : &Config) -> Vec<(LogFile, PathBuf)> {
+    let mut log_paths = vec![(LogFile::BepInEx, Path::new(&config.among_us_path).join("BepInEx/LogOutput.log"))];
+    if let Some(player_log_path) = get_player_log_path(config) {
+        log_paths.push((LogFile::Player, player_log_path));
+    }
+    log_paths
+}
+
+struct TailedFile {
+    file : LogFile,
+    path : PathBuf,
+    offset : u64,
+    partial_line : String,
+    pending_entry : Option<LogEntry>
+}
+
+impl TailedFile {
+    /// Starts at the current end, because the file still contains the log of the previous session
+    fn new(file : LogFile, path : PathBuf) -> Self {
+        let offset = path.metadata().map(|m| m.len()).unwrap_or(0);
+        Self { file, path, offset, partial_line: String::new(), pending_entry: None }
+    }
+
+    /// Reads the lines that were added since the last call
+    fn read_new_lines(&mut self) -> Vec<String> {
+        let Ok(mut file) = std::fs::File::open(&self.path) else { return vec![]; };
+        let length = file.metadata().map(|m| m.len()).unwrap_or(0);
+        if length < self.offset {
+            // The game truncates the log when it starts
+            self.offset = 0;
+            self.partial_line.clear();
+        }
+        if length == self.offset || file.seek(SeekFrom::Start(self.offset)).is_err() { return vec![]; }
+        let mut data = vec![];
+        let Ok(read) = file.take(length - self.offset).read_to_end(&mut data) else { return vec![]; };
+        self.offset += read as u64;
+        self.partial_line.push_str(&String::from_utf8_lossy(&data));
+        let mut lines : Vec<String> = self.partial_line.split('\n').map(|l| l.trim_end_matches('\r').to_string()).collect();
+        // The last line is not complete yet
+        self.partial_line = lines.pop().unwrap_or_default();
+        lines
+    }
+}
+
+async fn publish_entry(mut entry : LogEntry, signatures : &[ModSignature], window : &Window) {
+    entry.mod_name = attribute_entry(&entry, signatures);
+    if entry.mod_name.is_some() {
+        *LAST_ERROR_MOD.lock().await = entry.mod_name.clone();
+    }
+    let mut log_entries = LOG_ENTRIES.lock().await;
+    if log_entries.len() >= MAX_LOG_ENTRIES {
+        log_entries.pop_front();
+    }
+    log_entries.push_back(entry.clone());
+    drop(log_entries);
+    window.emit("log", entry).unwrap();
+}
+
+async fn tail_loop(mut tailed_files : Vec<TailedFile>, signatures : Vec<ModSignature>, window : Window) {
+    loop {
+        for tailed_file in tailed_files.iter_mut() {
+            let lines = tailed_file.read_new_lines();
+            // An entry is only complete once the next one starts or nothing was added for a while
+            if lines.is_empty() {
+                if let Some(entry) = tailed_file.pending_entry.take() {
+                    publish_entry(entry, &signatures, &window).await;
+                }
+                continue;
+            }
+            for line in lines {
+                match parse_line(tailed_file.file, &line) {
+                    Some(entry) => {
+                        if let Some(previous_entry) = tailed_file.pending_entry.replace(entry) {
+                            publish_entry(previous_entry, &signatures, &window).await;
+                        }
+                    },
+                    None => match &mut tailed_file.pending_entry {
+                        Some(entry) if !line.is_empty() => {
+                            entry.message.push('\n');
+                            entry.message.push_str(&line);
+                        },
+                        _ => {}
+                    }
+                }
+            }
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(POLL_INTERVAL_MS)).await;
+    }
+}
+
+/// Starts following the logs of a session. The returned task runs until it is aborted.
+pub async fn start_tailing(config : &Config, window : Window) -> JoinHandle<()> {
+    LOG_ENTRIES.lock().await.clear();
+    *LAST_ERROR_MOD.lock().await = None;
+    let tailed_files = get_log_paths(config).into_iter().map(|(file, path)| TailedFile::new(file, path)).collect();
+    tokio::spawn(tail_loop(tailed_files, get_mod_signatures(config), window))
+}
+
+/// Stops following the logs, after giving the game a moment to flush what it wrote before exiting
+pub async fn stop_tailing(tail : JoinHandle<()>) {
+    tokio::time::sleep(tokio::time::Duration::from_millis(POLL_INTERVAL_MS * 3)).await;
+    tail.abort();
+}
+
+pub async fn get_entries() -> Vec<LogEntry> {
+    LOG_ENTRIES.lock().await.iter().cloned().collect()
+}
+
+/// The mod that caused the last error of the session, if the error could be traced back to one
+pub async fn get_last_error_mod() -> Option<String> {
+    LAST_ERROR_MOD.lock().await.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_signatures() -> Vec<ModSignature> {
+        vec![
+            ModSignature {
+                name: "TheOtherRoles".to_string(),
+                plugin_names: vec!["The Other Roles".to_string()],
+                needles: vec!["me.eisbison.theotherroles".to_string(), "TheOtherRoles".to_string()]
+            },
+            ModSignature {
+                name: "Town Of Us".to_string(),
+                plugin_names: vec!["TownOfUs".to_string()],
+                needles: vec!["com.slushiegoose.townofus".to_string(), "TownOfUs".to_string()]
+            },
+            ModSignature { name: "Reactor".to_string(), plugin_names: vec!["Reactor".to_string()], needles: vec!["gg.reactor.api".to_string()] }
+        ]
+    }
+
+    fn parse_and_attribute(file : LogFile, line : &str) -> Option<String> {
+        attribute_entry(&parse_line(file, line).unwrap(), &get_signatures())
+    }
+
+    #[test]
+    fn parses_bepinex_headers() {
+        assert_eq!(
+            parse_bepinex_header("[Error  :The Other Roles] Failed to load hats"),
+            Some(("Error".to_string(), "The Other Roles".to_string(), "Failed to load hats".to_string()))
+        );
+        // Only the first bracket closes the header
+        assert_eq!(
+            parse_bepinex_header("[Info   :   BepInEx] Loading [TownOfUs 3.4.0]"),
+            Some(("Info".to_string(), "BepInEx".to_string(), "Loading [TownOfUs 3.4.0]".to_string()))
+        );
+        assert_eq!(parse_bepinex_header("[Warning] no source"), None);
+        assert_eq!(parse_bepinex_header("   at TheOtherRoles.Patches.HatsPatch.Postfix () [0x00000] in <abc>:0"), None);
+    }
+
+    #[test]
+    fn parses_bepinex_lines() {
+        let entry = parse_line(LogFile::BepInEx, "[Message:   BepInEx] Chainloader startup complete").unwrap();
+        assert_eq!((entry.level.as_str(), entry.source.as_str(), entry.message.as_str()), ("Message", "BepInEx", "Chainloader startup complete"));
+        assert!(!entry.is_error());
+        // Lines of a stack trace continue the previous entry
+        assert!(parse_line(LogFile::BepInEx, "System.NullReferenceException: Object reference not set to an instance of an object.").is_none());
+        assert!(parse_line(LogFile::BepInEx, "  at TownOfUs.Roles.Role.Update () [0x00000] in <abc>:0").is_none());
+    }
+
+    #[test]
+    fn parses_unity_player_lines() {
+        let entry = parse_line(LogFile::Player, "NullReferenceException: Object reference not set to an instance of an object").unwrap();
+        assert_eq!((entry.level.as_str(), entry.source.as_str()), ("Error", "Unity"));
+        let entry = parse_line(LogFile::Player, "Initialize engine version: 2020.3.45f1 (660cd1701bd5)").unwrap();
+        assert_eq!(entry.level, "Info");
+        for line in ["", "  at TownOfUs.Roles.Role.Update () [0x00000] in <abc>:0", "(Filename: <abc> Line: 0)"] {
+            assert!(parse_line(LogFile::Player, line).is_none(), "{:?} started an entry", line);
+        }
+    }
+
+    #[test]
+    fn attributes_errors_to_the_plugin_that_logged_them() {
+        assert_eq!(parse_and_attribute(LogFile::BepInEx, "[Error  :The Other Roles] Failed to load hats"), Some("TheOtherRoles".to_string()));
+        // The source wins over a mod mentioned in the message
+        assert_eq!(parse_and_attribute(LogFile::BepInEx, "[Fatal  :Reactor] TownOfUs registered an invalid RPC"), Some("Reactor".to_string()));
+    }
+
+    #[test]
+    fn attributes_errors_by_the_mods_in_their_stack_trace() {
+        let mut entry = parse_line(LogFile::Player, "NullReferenceException: Object reference not set to an instance of an object").unwrap();
+        entry.message.push_str("\n  at TownOfUs.Roles.Role.Update () [0x00000] in <abc>:0");
+        assert_eq!(attribute_entry(&entry, &get_signatures()), Some("Town Of Us".to_string()));
+        assert_eq!(parse_and_attribute(LogFile::BepInEx, "[Error  :Il2CppInterop] Hook for gg.reactor.api failed"), Some("Reactor".to_string()));
+    }
+
+    #[test]
+    fn does_not_attribute_other_entries() {
+        // Not an error
+        assert_eq!(parse_and_attribute(LogFile::BepInEx, "[Info   :The Other Roles] Loaded 40 hats"), None);
+        // An error no mod can be blamed for
+        assert_eq!(parse_and_attribute(LogFile::BepInEx, "[Error  :Unity Log] Failed to connect to the matchmaker"), None);
+        assert_eq!(parse_and_attribute(LogFile::Player, "NullReferenceException: Object reference not set to an instance of an object"), None);
+    }
+}
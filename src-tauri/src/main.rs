@@ -2,14 +2,35 @@
   all(not(debug_assertions), target_os = "windows"),
   windows_subsystem = "windows"
 )]
+#[macro_use]
+mod launcher_log;
+mod archive;
+mod backup;
+mod bepinex;
+mod bepinex_config;
+mod blob_store;
 mod config;
+mod diagnostics;
+mod doctor;
+mod dotnet;
+mod epic;
+mod itch;
+mod launch;
+mod logs;
 mod mod_manager;
 mod util;
+mod version_info;
 mod github_api;
+mod pe;
+mod session;
+mod sniff;
+mod steam;
+mod sync;
+mod watcher;
+mod wine;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use sysinfo::{System, SystemExt};
 use tokio::sync::Mutex;
 use serde::{Serialize, Deserialize};
 use tauri::{State, Window};
@@ -18,13 +39,22 @@ use crate::config::Config;
 use crate::mod_manager::Mod;
 
 type GlobalConfig = Arc<Mutex<Config>>;
+/// Held while backups are written or removed, so collecting garbage never removes blobs of a backup that is being made
+type BackupLock = Arc<Mutex<()>>;
 const AMONG_US_STEAM_ID : &'static str = "945360";
 
 static KNOWN_MODS : OnceCell<Vec<KnownMod>> = OnceCell::new();
 
 #[tauri::command]
 async fn play(window: tauri::Window, config: State<'_, GlobalConfig>) -> Result<(), String> {
+    session::ensure_game_not_running()?;
+    let global_config = config.inner().clone();
     let mut config = config.lock().await;
+    // The backup is what the game folder is reverted to after a temporary session
+    let snapshot_path = if config.launch.temporary_session { Some(backup::resolve_snapshot_path(&config, None)?) } else { None };
+    if snapshot_path.as_ref().map(|p| !backup::is_complete(p)).unwrap_or(false) {
+        return Err("A temporary session needs a complete backup of the installed build of the game".to_string());
+    }
     // Uninstall old mods
     for index in 0..config.downloaded.len() {
         let mut modification = std::mem::take(&mut config.downloaded[index]);
@@ -35,7 +65,16 @@ async fn play(window: tauri::Window, config: State<'_, GlobalConfig>) -> Result<
         }
     }
     config.save();
+    // Dll mods are only plugins, so BepInEx has to be installed for them
+    let needs_bepinex = config.downloaded.iter().any(|m| m.enabled && matches!(m.mod_type, mod_manager::ModType::Dll));
+    if needs_bepinex && !bepinex::is_installed(Path::new(&config.among_us_path)) {
+        window.emit("progress", format!("Installing BepInEx")).unwrap();
+        let install = bepinex::install(&config.bepinex, Path::new(&config.among_us_path), &window).await?;
+        config.bepinex.record_install(install);
+        config.save();
+    }
     // Download and Install
+    let mut install_reports = vec![];
     for index in 0..config.downloaded.len() {
         let mut modification = std::mem::take(&mut config.downloaded[index]);
         match modification.download(&config, &window).await {
@@ -43,29 +82,241 @@ async fn play(window: tauri::Window, config: State<'_, GlobalConfig>) -> Result<
             Err(s) => {config.downloaded[index] = modification; return Err(s)}
         }
         window.emit("progress", format!("Installing {}", modification.name)).unwrap();
-        modification.install(&config, &window).await?;
+        let report = modification.install(&config, &window).await;
+        let enabled = modification.enabled;
         config.downloaded[index] = modification;
+        let report = report?;
+        if enabled {
+            install_reports.push(report);
+        }
     }
     config.save();
+    window.emit("install_reports", install_reports).unwrap();
     // Start Among Us
     window.emit("progress", format!("Sussing ...")).unwrap();
-    if config.run_with_steam {
-        open::that(format!("steam://rungameid/{}", AMONG_US_STEAM_ID))
-            .or(Err(format!("Could not start among us")))?;
-    } else {
-        open::that(Path::new(&config.among_us_path).join("Among Us.exe").display().to_string())
-            .or(Err(format!("Could not start among us")))?;
+    let command = config.launch.build_command(Path::new(&config.among_us_path))?;
+    log_info!("Starting Among Us with: {}", command);
+    let child = command.run(&window).await?;
+    let session = session::start(child, global_config.clone(), window.clone());
+    if let Some(snapshot_path) = snapshot_path {
+        session::revert_after_exit(session, snapshot_path, global_config, window);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_bepinex_settings(config: State<'_, GlobalConfig>) -> Result<bepinex::BepInExSettings, String> {
+    Ok(config.lock().await.bepinex.clone())
+}
+
+#[tauri::command]
+async fn set_bepinex_settings(source : bepinex::BepInExSource, pinned_version : Option<String>, config: State<'_, GlobalConfig>) -> Result<(), String> {
+    let mut config = config.lock().await;
+    config.bepinex.source = source;
+    config.bepinex.pinned_version = pinned_version;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn install_bepinex(window: tauri::Window, config: State<'_, GlobalConfig>) -> Result<String, String> {
+    session::ensure_game_not_running()?;
+    let mut config = config.lock().await;
+    let install = bepinex::install(&config.bepinex, Path::new(&config.among_us_path), &window).await?;
+    let version = install.version.clone();
+    config.bepinex.record_install(install);
+    config.save();
+    Ok(version)
+}
+
+#[tauri::command]
+async fn update_bepinex(window: tauri::Window, config: State<'_, GlobalConfig>) -> Result<Option<String>, String> {
+    session::ensure_game_not_running()?;
+    let mut config = config.lock().await;
+    let Some(install) = bepinex::update(&config.bepinex, Path::new(&config.among_us_path), &window).await? else {
+        return Ok(None);
+    };
+    let version = install.version.clone();
+    config.bepinex.record_install(install);
+    config.save();
+    Ok(Some(version))
+}
+
+#[tauri::command]
+async fn verify_bepinex(config: State<'_, GlobalConfig>) -> Result<bepinex::BepInExStatus, String> {
+    let config = config.lock().await;
+    Ok(bepinex::verify(&config.bepinex, Path::new(&config.among_us_path)))
+}
+
+#[tauri::command]
+async fn uninstall_bepinex(config: State<'_, GlobalConfig>) -> Result<(), String> {
+    session::ensure_game_not_running()?;
+    let mut config = config.lock().await;
+    bepinex::uninstall(&config.bepinex, Path::new(&config.among_us_path)).await?;
+    config.bepinex.forget_install();
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_log_entries() -> Vec<logs::LogEntry> {
+    logs::get_entries().await
+}
+
+#[tauri::command]
+async fn get_game_build(config: State<'_, GlobalConfig>) -> Result<Option<backup::GameBuild>, String> {
+    let config = config.lock().await;
+    Ok(backup::detect_build(Path::new(&config.among_us_path), config.storefront))
+}
+
+#[tauri::command]
+async fn list_backups(config: State<'_, GlobalConfig>) -> Result<Vec<backup::SnapshotInfo>, String> {
+    Ok(backup::list_snapshots(Path::new(&config.lock().await.backup_among_us_path)))
+}
+
+/// Backs up the installed build of the game
+#[tauri::command]
+async fn create_backup(config: State<'_, GlobalConfig>, backup_lock: State<'_, BackupLock>) -> Result<backup::SnapshotInfo, String> {
+    session::ensure_game_not_running()?;
+    let source = backup::BackupSource::new(&*config.lock().await);
+    let _backup_guard = backup_lock.lock().await;
+    sync::run_blocking(move || backup::create(&source)).await
+}
+
+#[tauri::command]
+async fn delete_backup(build_id : String, config: State<'_, GlobalConfig>, backup_lock: State<'_, BackupLock>) -> Result<(), String> {
+    let _backup_guard = backup_lock.lock().await;
+    backup::delete(Path::new(&config.lock().await.backup_among_us_path), &build_id)
+}
+
+#[tauri::command]
+async fn get_backup_storage_usage(config: State<'_, GlobalConfig>) -> Result<backup::StorageUsage, String> {
+    Ok(backup::get_storage_usage(Path::new(&config.lock().await.backup_among_us_path)))
+}
+
+/// Removes stored files that no backup needs anymore
+#[tauri::command]
+async fn collect_backup_garbage(config: State<'_, GlobalConfig>, backup_lock: State<'_, BackupLock>) -> Result<backup::StorageUsage, String> {
+    let backup_path = PathBuf::from(&config.lock().await.backup_among_us_path);
+    let _backup_guard = backup_lock.lock().await;
+    sync::run_blocking(move || backup::collect_garbage(&backup_path)).await
+}
+
+/// The build defaults to the installed one in the following commands
+#[tauri::command]
+async fn verify_backup(build_id : Option<String>, config: State<'_, GlobalConfig>) -> Result<backup::BackupStatus, String> {
+    let snapshot_path = backup::resolve_snapshot_path(&*config.lock().await, build_id.as_deref())?;
+    sync::run_blocking(move || Ok(backup::verify(&snapshot_path))).await
+}
+
+#[tauri::command]
+async fn get_game_changes(build_id : Option<String>, config: State<'_, GlobalConfig>) -> Result<backup::GameChanges, String> {
+    let config = config.lock().await;
+    let snapshot_path = backup::resolve_snapshot_path(&config, build_id.as_deref())?;
+    let among_us_path = PathBuf::from(&config.among_us_path);
+    sync::run_blocking(move || backup::get_changes(&among_us_path, &snapshot_path)).await
+}
+
+/// Restores the vanilla game, or only the given files if there are any.
+/// Only the backup of the installed build can be restored, writing another build over the game would downgrade it.
+#[tauri::command]
+async fn restore_game(build_id : Option<String>, paths : Option<Vec<String>>, config: State<'_, GlobalConfig>) -> Result<(), String> {
+    session::ensure_game_not_running()?;
+    let mut config = config.lock().await;
+    if let Some(build_id) = &build_id {
+        let installed_build = backup::detect_build(Path::new(&config.among_us_path), config.storefront).ok_or("Cannot detect the build of Among Us")?;
+        if *build_id != installed_build.id {
+            return Err(format!("The backup of {} cannot be restored over the installed build {}", build_id, installed_build.id));
+        }
+    }
+    let snapshot_path = backup::resolve_snapshot_path(&config, None)?;
+    let restore_everything = paths.is_none();
+    // Mod settings would be lost with the config folder
+    if restore_everything {
+        for modification in &config.downloaded {
+            modification.snapshot_configs(&config).await?;
+        }
+    }
+    let among_us_path = PathBuf::from(&config.among_us_path);
+    sync::run_blocking(move || backup::restore(&among_us_path, &snapshot_path, paths)).await?;
+    if restore_everything {
+        config.forget_installed_files();
+        config.save();
     }
     Ok(())
 }
 
+#[tauri::command]
+async fn run_doctor(config: State<'_, GlobalConfig>) -> Result<Vec<doctor::CheckResult>, String> {
+    Ok(doctor::run(&*config.lock().await).await)
+}
+
+#[tauri::command]
+async fn apply_doctor_fix(fix : doctor::Fix, window: tauri::Window, config: State<'_, GlobalConfig>, backup_lock: State<'_, BackupLock>) -> Result<(), String> {
+    let mut config = config.lock().await;
+    doctor::apply_fix(fix, &mut config, &backup_lock, &window).await
+}
+
+/// Writes a zip with everything needed to debug a broken install and returns where it was written
+#[tauri::command]
+async fn export_diagnostics(output_path : Option<String>, config: State<'_, GlobalConfig>) -> Result<String, String> {
+    let output_path = output_path.map(PathBuf::from).unwrap_or_else(|| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        PathBuf::from(format!("diagnostics_{}.zip", now))
+    });
+    // Hashing the plugins takes a while, so the export works on a copy of the config
+    let config = config.lock().await.clone();
+    let bundle_path = output_path.clone();
+    sync::run_blocking(move || diagnostics::export(&config, &bundle_path)).await?;
+    log_info!("Diagnostics written to {}", output_path.display());
+    Ok(output_path.display().to_string())
+}
+
+#[tauri::command]
+async fn get_session_history(config: State<'_, GlobalConfig>) -> Result<Vec<session::SessionRecord>, String> {
+    Ok(config.lock().await.session_history.clone())
+}
+
+#[tauri::command]
+async fn get_game_output() -> Vec<String> {
+    launch::get_game_output().await
+}
+
+#[tauri::command]
+async fn get_launch_options(config: State<'_, GlobalConfig>) -> Result<launch::LaunchOptions, String> {
+    Ok(config.lock().await.launch.clone())
+}
+
+#[tauri::command]
+async fn set_launch_options(options : launch::LaunchOptions, config: State<'_, GlobalConfig>) -> Result<(), String> {
+    options.validate()?;
+    let mut config = config.lock().await;
+    config.launch = options;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_launch_support(strategy : launch::LaunchStrategy) -> launch::LaunchSupport {
+    strategy.get_support()
+}
+
+#[tauri::command]
+async fn preview_launch_command(config: State<'_, GlobalConfig>) -> Result<String, String> {
+    let config = config.lock().await;
+    Ok(config.launch.build_command(Path::new(&config.among_us_path))?.to_string())
+}
+
 #[tauri::command]
 async fn update_mod_config(index : usize, mut new_mod : Mod, config: State<'_, GlobalConfig>) -> Result<(), String> {
+    session::ensure_game_not_running()?;
+    archive::check_name(&new_mod.name).map_err(|e| format!("Invalid mod name: {}", e))?;
     let mut config = config.lock().await;
-    if config.downloaded[index].enabled && !new_mod.enabled {
+    let modification = config.downloaded.get_mut(index).ok_or("Mod does not exist")?;
+    if modification.enabled && !new_mod.enabled {
         new_mod.do_uninstall = true;
     }
-    config.downloaded[index] = new_mod;
+    *modification = new_mod;
     config.save();
     Ok(())
 }
@@ -77,6 +328,7 @@ async fn get_mods(config: State<'_, GlobalConfig>) -> Result<Vec<Mod>, String> {
 
 #[tauri::command]
 async fn add_mod(name : String, location : String, version: String, config: State<'_, GlobalConfig>) -> Result<(), String> {
+    session::ensure_game_not_running()?;
     let new_mod = Mod::new(name, &location, version).await?;
     let mut config = config.lock().await;
     if config.downloaded.iter().any(|m| m.name == new_mod.name) {
@@ -89,24 +341,102 @@ async fn add_mod(name : String, location : String, version: String, config: Stat
 
 #[tauri::command]
 async fn remove_mod(index : usize, config: State<'_, GlobalConfig>) -> Result<(), String> {
+    session::ensure_game_not_running()?;
     let mut config = config.lock().await;
+    if index >= config.downloaded.len() {
+        return Err("Mod does not exist".to_string());
+    }
     config.save();
     let mut mod_to_remove = config.downloaded.remove(index);
+    watcher::unwatch(&mod_to_remove.name).await;
     mod_to_remove.remove(&config).await?;
     Ok(())
 }
 
+#[tauri::command]
+async fn get_mod_assembly_info(index : usize, config: State<'_, GlobalConfig>) -> Result<dotnet::AssemblyInfo, String> {
+    let config = config.lock().await;
+    match config.downloaded.get(index) {
+        Some(modification) => modification.get_assembly_info(&config).await,
+        None => Err("Mod does not exist".to_string())
+    }
+}
+
+/// Only config files that belong to the mod can be accessed
+fn get_mod_config_path(config : &Config, index : usize, file_name : &str) -> Result<std::path::PathBuf, String> {
+    let modification = config.downloaded.get(index).ok_or("Mod does not exist")?;
+    modification.get_config_paths(config).into_iter()
+        .find(|p| p.file_name().map(|n| n == file_name).unwrap_or(false))
+        .ok_or(format!("{} has no config file {}", modification.name, file_name))
+}
+
+#[tauri::command]
+async fn list_mod_configs(index : usize, config: State<'_, GlobalConfig>) -> Result<Vec<String>, String> {
+    let config = config.lock().await;
+    let modification = config.downloaded.get(index).ok_or("Mod does not exist")?;
+    Ok(modification.get_config_paths(&config).iter()
+        .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+        .collect())
+}
+
+#[tauri::command]
+async fn read_mod_config(index : usize, file_name : String, config: State<'_, GlobalConfig>) -> Result<Vec<bepinex_config::ConfigSetting>, String> {
+    let config = config.lock().await;
+    let config_path = get_mod_config_path(&config, index, &file_name)?;
+    Ok(bepinex_config::ConfigFile::read(&config_path)?.get_settings())
+}
+
+#[tauri::command]
+async fn write_mod_config(index : usize, file_name : String, changes : Vec<bepinex_config::ConfigChange>, config: State<'_, GlobalConfig>) -> Result<(), String> {
+    session::ensure_game_not_running()?;
+    let config = config.lock().await;
+    let config_path = get_mod_config_path(&config, index, &file_name)?;
+    let mut config_file = bepinex_config::ConfigFile::read(&config_path)?;
+    // Apply all changes before writing, so an invalid value doesn't leave the file half changed
+    for change in changes {
+        config_file.set(&change.section, &change.key, &change.value)?;
+    }
+    config_file.write(&config_path)
+}
+
+#[tauri::command]
+async fn get_steam_install() -> Option<steam::SteamInstall> {
+    steam::find_among_us()
+}
+
 #[tauri::command]
 async fn is_among_us_running() -> bool {
-    let mut sys = System::new();
-    sys.refresh_processes();
-    sys.processes_by_name("Among Us").count() > 0
+    session::is_game_running()
+}
+
+#[tauri::command]
+async fn watch_mod(index : usize, watch_path : Option<String>, window: tauri::Window, config: State<'_, GlobalConfig>) -> Result<(), String> {
+    session::ensure_game_not_running()?;
+    let name = match config.lock().await.downloaded.get(index) {
+        Some(modification) => modification.name.clone(),
+        None => return Err("Mod does not exist".to_string())
+    };
+    watcher::watch(name, watch_path, config.inner().clone(), window).await
+}
+
+#[tauri::command]
+async fn unwatch_mod(name : String) -> Result<(), String> {
+    watcher::unwatch(&name).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_watched_mods() -> Vec<watcher::WatchedMod> {
+    watcher::get_watched().await
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KnownMod {
     name : String,
-    location : String
+    location : String,
+    /// GUID from the `[BepInPlugin]` attribute, used to recognize the mod even if its dll was renamed
+    #[serde(default)]
+    guid : Option<String>
 }
 
 #[tauri::command]
@@ -119,22 +449,40 @@ async fn get_possible_mods(name : String) -> Vec<KnownMod> {
     }).take(20).map(|m| m.clone()).collect()
 }
 
-async fn on_page_load(window: Window, config: Arc<Mutex<Config>>) {
+async fn on_page_load(window: Window, config: Arc<Mutex<Config>>, backup_lock: BackupLock) {
     let mut config = config.lock().await;
     if config.among_us_path.len() == 0 {
         match config::find_among_us_path(&window) {
-            Some(among_us_path) => {
+            Some((among_us_path, storefront)) => {
                 config.among_us_path = among_us_path;
+                config.storefront = storefront;
+                config.launch.strategy = launch::LaunchStrategy::for_storefront(storefront);
                 config.save();
             },
             None => {}
         }
+    } else if config.storefront == config::Storefront::Unknown {
+        // Configs from older versions don't know where the game came from yet
+        config.storefront = config::detect_storefront(Path::new(&config.among_us_path));
+        config.save();
+    }
+    // Backup among us folder on first run and after every update of the game
+    let backup_guard = backup_lock.lock().await;
+    if let Err(e) = backup::migrate(Path::new(&config.backup_among_us_path)) {
+        log_error!("Cannot move the old backup: {}", e);
     }
-    // Backup among us folder on first run
-    if !std::path::Path::new(&config.backup_among_us_path).exists() {
-        window.emit("load","Backing up").unwrap();
-        util::copy_folder(Path::new(&config.among_us_path), &Path::new(&config.backup_among_us_path));
+    match backup::resolve_snapshot_path(&config, None) {
+        Ok(snapshot_path) if !backup::is_complete(&snapshot_path) => {
+            window.emit("load","Backing up").unwrap();
+            let source = backup::BackupSource::new(&config);
+            if let Err(e) = sync::run_blocking(move || backup::create(&source)).await {
+                log_error!("Cannot back up Among Us folder: {}", e);
+            }
+        },
+        Ok(_) => {},
+        Err(e) => log_error!("Cannot back up Among Us folder: {}", e)
     }
+    drop(backup_guard);
     // Try to detect preinstalled mods
     config.add_previously_installed_mods(&window).await;
     // Wait a bit because that's apparently needed for the app not to freeze 🥶
@@ -144,18 +492,22 @@ async fn on_page_load(window: Window, config: Arc<Mutex<Config>>) {
 
 #[tokio::main]
 async fn main() {
+    launcher_log::init();
     let mut config = Config::load();
     for modification in &mut config.downloaded {
         modification.update_newest_version().await;
     }
     KNOWN_MODS.set(util::load_known_mods().await).unwrap();
     let config = Arc::new(Mutex::new(config));
+    let backup_lock = BackupLock::default();
     tauri::Builder::default()
         .manage(config.clone())
+        .manage(backup_lock.clone())
         .on_page_load(move |window, _| {
             let config_clone = config.clone();
+            let backup_lock_clone = backup_lock.clone();
             tokio::spawn(async move {
-                on_page_load(window, config_clone).await
+                on_page_load(window, config_clone, backup_lock_clone).await
             });
         })
         .invoke_handler(tauri::generate_handler!(
@@ -165,7 +517,40 @@ async fn main() {
             add_mod,
             remove_mod,
             is_among_us_running,
-            get_possible_mods))
+            get_possible_mods,
+            watch_mod,
+            unwatch_mod,
+            get_watched_mods,
+            get_mod_assembly_info,
+            get_steam_install,
+            get_launch_options,
+            set_launch_options,
+            get_launch_support,
+            preview_launch_command,
+            get_game_output,
+            get_session_history,
+            get_bepinex_settings,
+            set_bepinex_settings,
+            install_bepinex,
+            update_bepinex,
+            verify_bepinex,
+            uninstall_bepinex,
+            list_mod_configs,
+            read_mod_config,
+            write_mod_config,
+            get_log_entries,
+            export_diagnostics,
+            run_doctor,
+            apply_doctor_fix,
+            get_game_build,
+            list_backups,
+            create_backup,
+            delete_backup,
+            get_backup_storage_usage,
+            collect_backup_garbage,
+            verify_backup,
+            get_game_changes,
+            restore_game))
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
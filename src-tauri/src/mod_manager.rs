@@ -1,12 +1,21 @@
 use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use tauri::Window;
+use walkdir::WalkDir;
 use crate::config::Config;
-use crate::{github_api, util};
-use crate::util::copy_folder;
+use crate::backup::{self, BackupManifest};
+use crate::{archive, github_api, sniff, sync, util};
+use crate::dotnet::{AssemblyInfo, PluginInfo};
+use crate::bepinex_config::ConfigFile;
 use crate::mod_manager::ModLocation::{Github, Local};
 
+/// Config files of mods are kept here while the mod is uninstalled or updated
+const CONFIG_SNAPSHOTS_PATH : &'static str = "./mod_configs";
+/// Game files that were overwritten by a mod are kept here, so they can be put back when the mod is uninstalled
+const ORIGINALS_PATH : &'static str = "./mod_originals";
+/// New builds of watched mods are downloaded here. It is outside of the mods folder, so it can't collide with the folder of a mod.
+const STAGING_PATH : &'static str = "./mod_staging";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ModLocation {
     Github(String, String),
@@ -49,6 +58,32 @@ impl Default for ModType {
     fn default() -> Self { Self::Files }
 }
 
+/// What happens to files that are already in the game folder, when a files mod is installed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InstallPolicy {
+    /// Overwritten files can't be put back, so they are left behind when the mod is uninstalled
+    Overwrite,
+    Skip,
+    /// Overwrites them, but saves the originals first, so uninstalling the mod puts them back
+    BackupAndOverwrite
+}
+
+impl Default for InstallPolicy {
+    fn default() -> Self { Self::BackupAndOverwrite }
+}
+
+/// What installing a mod did to the game folder
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InstallReport {
+    pub name : String,
+    /// Files that were replaced by the mod, relative to the game folder
+    pub overwritten : Vec<PathBuf>,
+    /// Overwritten files whose originals were saved by this install
+    pub saved_originals : Vec<PathBuf>,
+    /// Files that were left alone, because they were already there
+    pub skipped : u64
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Mod {
     pub name : String,
@@ -58,7 +93,19 @@ pub struct Mod {
     pub mod_type : ModType,
     pub enabled : bool,
     pub do_uninstall : bool,
-    pub do_update : bool
+    pub do_update : bool,
+    /// Folder inside the mod archive, that should be treated as the game folder. Detected automatically if not set.
+    #[serde(default)]
+    pub subpath : Option<String>,
+    /// GUIDs of the BepInEx plugins this mod contains, read from their `[BepInPlugin]` attributes
+    #[serde(default)]
+    pub plugin_guids : Vec<String>,
+    #[serde(default)]
+    pub install_policy : InstallPolicy,
+    /// Files this files mod put into the game folder, relative to it. Overwritten files of the game are not in here.
+    /// `None` for mods installed before the launcher recorded them.
+    #[serde(default)]
+    pub installed_files : Option<Vec<PathBuf>>
 }
 
 impl Mod {
@@ -70,6 +117,7 @@ impl Mod {
         if location_string.len() == 0 {
             return Err("Empty Location".to_string())
         }
+        archive::check_name(&name).map_err(|e| format!("Invalid mod name: {}", e))?;
 
         let location = ModLocation::new(location_string);
         let mod_type : ModType;
@@ -78,20 +126,18 @@ impl Mod {
                 let client = util::get_reqwest_client();
                 let repo_json = github_api::make_github_api_request(&client, format!("repos/{}/{}", username, repository_name)).await?;
                 name = repo_json.as_object().unwrap().get("name").unwrap().as_str().unwrap().to_string();
+                archive::check_name(&name).map_err(|e| format!("Invalid mod name: {}", e))?;
                 let newest_release = github_api::get_newest_release(&client, username, repository_name).await?;
                 version = newest_release.get("tag_name").unwrap().as_str().unwrap().to_string();
                 let assets = github_api::get_assets(&newest_release);
-                mod_type = get_mod_type(&assets[0])?;
                 if assets.len() == 0 {return Err("No assets found".to_string())}
+                // Only a guess, the actual type is known once the mod is downloaded
+                mod_type = sniff::classify_asset(&assets[0]).unwrap_or_default();
             },
             ModLocation::Local(path_string) => {
                 let path = std::path::Path::new(path_string);
                 if path.is_file() {
-                    mod_type = match path.extension().unwrap().to_str().unwrap() {
-                        "zip" => ModType::Files,
-                        "dll" => ModType::Dll,
-                        e => {return Err(format!("Invalid mod file extension : {}", e))}
-                    };
+                    mod_type = sniff::classify_file(path).await?;
                 } else {
                     return Err(format!("Path for mod {} is not a file", name))
                 }
@@ -105,32 +151,42 @@ impl Mod {
             mod_type,
             enabled: true,
             do_uninstall: false,
-            do_update: false
+            do_update: false,
+            subpath: None,
+            plugin_guids: vec![],
+            install_policy: InstallPolicy::default(),
+            installed_files: Some(vec![])
         })
     }
 
     pub async fn download(&mut self, config : &Config, window : &Window) -> Result<(), String> {
         if !self.enabled { return Ok(()); }
         let update = self.do_update && self.version != self.newest_version && self.enabled;
-        let mod_folder = Path::new(&config.mods_path).join(Path::new(&self.name));
-        if !mod_folder.exists() {
+        let mod_folder = self.get_mod_folder(config)?;
+        // An empty folder is left behind, if a download failed
+        if !self.is_downloaded(config) {
             tokio::fs::create_dir_all(&mod_folder).await.unwrap();
         } else if update {
+            self.snapshot_configs(config).await?;
             tokio::fs::remove_dir_all(&mod_folder).await.unwrap();
             tokio::fs::create_dir_all(&mod_folder).await.unwrap();
         } else {
             return Ok(()); // Already downloaded
         }
+        self.fetch(&mod_folder, window).await
+    }
 
+    /// Downloads or copies the mod into a folder and extracts it there
+    async fn fetch(&mut self, mod_folder : &Path, window : &Window) -> Result<(), String> {
         let output_path  : PathBuf = match &self.location {
             Github(username, repository_name) => {
                 let client = util::get_reqwest_client();
                 let newest_release = github_api::get_newest_release(&client, username, repository_name).await?;
                 let assets = github_api::get_assets(&newest_release);
-                let mod_asset = &assets[0];
-                self.mod_type = get_mod_type(&mod_asset)?;
+                let Some(mod_asset) = assets.first() else {return Err("No assets found".to_string())};
                 let output_file_name = mod_asset.get("name").unwrap().as_str().unwrap();
-                println!("Downloading : {}", output_file_name);
+                archive::check_name(output_file_name).map_err(|e| format!("Invalid asset name: {}", e))?;
+                log_info!("Downloading : {}", output_file_name);
                 let download_url = mod_asset.get("browser_download_url").unwrap().as_str().unwrap();
                 let output_file_path = mod_folder.join(output_file_name);
                 util::download_file(&client, download_url, &output_file_path, window, &self.name).await?;
@@ -146,106 +202,297 @@ impl Mod {
                 output_path
             }
         };
-        let extension = output_path.extension().unwrap().to_str().unwrap();
-        match extension {
-            "zip" => {
-                println!("Extracting : {}", output_path.display());
-                window.emit("progress", format!("Extracting")).unwrap();
-                let zip_file = std::fs::File::open(&output_path).or(Err(format!("Cannot open Zip file")))?;
-                let mut zip_reader = zip::read::ZipArchive::new(zip_file).or(Err(format!("Cannot read zip file")))?;
-                zip_reader.extract(mod_folder).or(Err(format!("Cannot extract zip file")))?;
-                tokio::fs::remove_file(output_path).await.or(Err(format!("Could not remove zip file")))?;
-            },
-            _ => {}
+        self.mod_type = sniff::classify_file(&output_path).await?;
+        if archive::ArchiveFormat::detect(&output_path).is_some() {
+            log_info!("Extracting : {}", output_path.display());
+            archive::extract(&output_path, mod_folder, self.subpath.as_deref(), window)?;
+            tokio::fs::remove_file(output_path).await.or(Err(format!("Could not remove archive file")))?;
         }
         self.do_update = false;
         self.version = self.newest_version.clone();
         Ok(())
     }
 
-    fn get_mod_folder(&self, config : &Config) -> PathBuf {
-        Path::new(&config.mods_path).join(Path::new(&self.name))
+    pub fn get_mod_folder(&self, config : &Config) -> Result<PathBuf, String> {
+        archive::check_name(&self.name).map_err(|e| format!("Invalid mod name: {}", e))?;
+        Ok(Path::new(&config.mods_path).join(Path::new(&self.name)))
+    }
+
+    pub fn is_downloaded(&self, config : &Config) -> bool {
+        self.get_mod_folder(config).ok()
+            .and_then(|f| f.read_dir().ok())
+            .map(|mut r| r.next().is_some())
+            .unwrap_or(false)
+    }
+
+    fn get_dll_path(&self, config : &Config) -> Result<PathBuf, String> {
+        let mod_folder = self.get_mod_folder(config)?;
+        match mod_folder.read_dir().ok().and_then(|mut r| r.next()).and_then(|e| e.ok()) {
+            Some(entry) => Ok(entry.path()),
+            None => Err(format!("{} has not been downloaded, there is no dll in {}", self.name, mod_folder.display()))
+        }
+    }
+
+    /// Reads the .NET metadata of a dll mod, failing if the dll is not a managed assembly
+    pub async fn get_assembly_info(&self, config : &Config) -> Result<AssemblyInfo, String> {
+        if !matches!(self.mod_type, ModType::Dll) {
+            return Err(format!("{} is not a dll mod", self.name));
+        }
+        let dll_path = self.get_dll_path(config)?;
+        let data = tokio::fs::read(&dll_path).await.or(Err(format!("Cannot read {}", dll_path.display())))?;
+        AssemblyInfo::read(&data).map_err(|e| format!("{} is not a valid .NET assembly: {}", dll_path.display(), e))
+    }
+
+    /// Reads the `[BepInPlugin]` attributes of all plugin dlls in the downloaded mod
+    pub fn read_plugin_infos(&self, config : &Config) -> Vec<PluginInfo> {
+        self.get_plugin_dll_paths(config).iter().filter_map(|p| PluginInfo::read_file(p).ok()).flatten().collect()
+    }
+
+    /// The plugin dlls of the downloaded mod
+    pub fn get_plugin_dll_paths(&self, config : &Config) -> Vec<PathBuf> {
+        match self.mod_type {
+            ModType::Dll => self.get_dll_path(config).into_iter().collect(),
+            ModType::Files => self.get_mod_folder(config)
+                .ok()
+                .and_then(|f| f.join("BepInEx/plugins").read_dir().ok())
+                .map(|r| r.filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().map(|e| e == "dll").unwrap_or(false))
+                    .collect()
+                ).unwrap_or_default()
+        }
+    }
+
+    /// The files the mod puts into the game folder, relative to it
+    pub fn get_installed_files(&self, config : &Config) -> Vec<PathBuf> {
+        match self.mod_type {
+            ModType::Dll => self.get_plugin_files(config).into_iter().map(|p| Path::new("BepInEx/plugins").join(p)).collect(),
+            ModType::Files => {
+                if let Some(installed_files) = &self.installed_files {
+                    return installed_files.clone();
+                }
+                let Ok(mod_folder) = self.get_mod_folder(config) else { return vec![] };
+                WalkDir::new(&mod_folder).into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file())
+                    .filter_map(|e| pathdiff::diff_paths(e.path(), &mod_folder))
+                    .collect()
+            }
+        }
+    }
+
+    /// The files the mod puts into `BepInEx/plugins`, relative to that folder
+    pub fn get_plugin_files(&self, config : &Config) -> Vec<PathBuf> {
+        match self.mod_type {
+            ModType::Dll => self.get_dll_path(config).ok()
+                .and_then(|p| p.file_name().map(PathBuf::from))
+                .into_iter().collect(),
+            ModType::Files => {
+                let Ok(mod_folder) = self.get_mod_folder(config) else { return vec![] };
+                let plugins_folder = mod_folder.join("BepInEx/plugins");
+                WalkDir::new(&plugins_folder).into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file())
+                    .filter_map(|e| pathdiff::diff_paths(e.path(), &plugins_folder))
+                    .collect()
+            }
+        }
+    }
+
+    /// BepInEx names config files after the GUID of their plugin, so the configs of a mod are found through its plugins
+    pub fn get_config_paths(&self, config : &Config) -> Vec<PathBuf> {
+        let config_path = Path::new(&config.among_us_path).join("BepInEx/config");
+        self.plugin_guids.iter()
+            .filter(|g| archive::check_name(g).is_ok())
+            .map(|g| config_path.join(format!("{}.cfg", g)))
+            .filter(|p| p.is_file())
+            .collect()
     }
 
-    pub async fn install(&self, config : &Config, window : &Window) -> Result<(), String> {
-        if !self.enabled {return Ok(())}
-        let mod_folder = self.get_mod_folder(config);
+    fn get_config_snapshot_folder(&self) -> Result<PathBuf, String> {
+        archive::check_name(&self.name).map_err(|e| format!("Invalid mod name: {}", e))?;
+        Ok(Path::new(CONFIG_SNAPSHOTS_PATH).join(&self.name))
+    }
+
+    /// Saves the config files of the mod, so the settings survive an update or uninstall
+    pub async fn snapshot_configs(&self, config : &Config) -> Result<(), String> {
+        let config_paths = self.get_config_paths(config);
+        if config_paths.is_empty() { return Ok(()) }
+        let snapshot_folder = self.get_config_snapshot_folder()?;
+        sync::run_blocking(move || save_configs(&config_paths, &snapshot_folder)).await
+    }
+
+    /// Puts saved config files back, see [`restore_saved_configs`]
+    async fn restore_configs(&self, config : &Config) -> Result<(), String> {
+        let snapshot_folder = self.get_config_snapshot_folder()?;
+        let config_folder = Path::new(&config.among_us_path).join("BepInEx/config");
+        sync::run_blocking(move || restore_saved_configs(&snapshot_folder, &config_folder)).await
+    }
+
+    pub async fn install(&mut self, config : &Config, window : &Window) -> Result<InstallReport, String> {
+        self.install_files(config, window, self.install_policy).await
+    }
+
+    async fn install_files(&mut self, config : &Config, window : &Window, install_policy : InstallPolicy) -> Result<InstallReport, String> {
+        let mut report = InstallReport { name: self.name.clone(), ..Default::default() };
+        if !self.enabled {return Ok(report)}
+        let mod_folder = self.get_mod_folder(config)?;
         match self.mod_type {
-            ModType::Files => copy_folder(&mod_folder, &Path::new(&config.among_us_path)),
+            ModType::Files => {
+                let existing_files = match install_policy {
+                    InstallPolicy::Skip => sync::ExistingFiles::Skip,
+                    InstallPolicy::Overwrite | InstallPolicy::BackupAndOverwrite => sync::ExistingFiles::ReplaceChanged
+                };
+                let among_us_path = PathBuf::from(&config.among_us_path);
+                let mut plan = sync::plan_sync(&mod_folder, &among_us_path, existing_files).await?;
+                // The settings of the user are merged into the shipped configs by `restore_configs` instead
+                plan.keep_existing(|p| p.starts_with("BepInEx/config"));
+                // Mods installed before their files were recorded can't tell their own files apart from the ones of the game,
+                // so the vanilla backup decides which files belong to the game
+                let vanilla_manifest = match self.installed_files {
+                    Some(_) => None,
+                    None => backup::read_installed_manifest(config)
+                };
+                let mut installed_files = match self.installed_files.take() {
+                    Some(installed_files) => installed_files,
+                    None => {
+                        let (kept_files, among_us_path, vanilla_manifest) = (plan.get_kept_files(), among_us_path.clone(), vanilla_manifest.clone());
+                        sync::run_blocking(move || Ok(adopt_kept_files(&among_us_path, kept_files, vanilla_manifest.as_ref()))).await?
+                    }
+                };
+                // Files of an earlier version of this mod are not originals, putting them back would keep the mod installed
+                let replaced_files : Vec<(PathBuf, PathBuf)> = plan.get_replaced_files().into_iter()
+                    .filter(|(relative_path, _)| !installed_files.contains(relative_path))
+                    .collect();
+                let foreign_files : Vec<PathBuf> = match install_policy {
+                    InstallPolicy::BackupAndOverwrite => {
+                        let originals_folder = self.get_originals_folder()?;
+                        report.saved_originals = sync::run_blocking(move || save_originals(&originals_folder, &replaced_files, vanilla_manifest.as_ref())).await?;
+                        vec![]
+                    },
+                    _ => replaced_files.into_iter().map(|(relative_path, _)| relative_path).collect()
+                };
+                // Recorded before copying, so files of a failed install are removed with the mod
+                for path in plan.get_copied_files() {
+                    if !installed_files.contains(&path) && !foreign_files.contains(&path) {
+                        installed_files.push(path);
+                    }
+                }
+                self.installed_files = Some(installed_files);
+                let sync_report = sync::run_sync(plan, &self.name, window).await?;
+                report.overwritten = sync_report.replaced_files;
+                report.skipped = sync_report.skipped_files;
+            },
             ModType::Dll => {
                 let plugins_path = util::get_plugins_path(config);
                 if !plugins_path.exists() {
-                    // BepInEx is not installed so all the files required to use it will need to be downloaded and extracted
-                    // Download
-                    let client = util::get_reqwest_client();
-                    // TODO: Check if it might also be possible to get BepInEx directly from https://builds.bepis.io/projects/bepinex_be
-                    let release = github_api::get_newest_release(&client, &"NuclearPowered".to_string(), &"BepInEx".to_string()).await?;
-                    let assets= github_api::get_assets(&release).remove(0);
-                    let download_url = assets.get("browser_download_url").unwrap().as_str().unwrap();
-                    let among_us_path = Path::new(&config.among_us_path);
-                    let zip_file_path = among_us_path.join("BepInEx.zip");
-                    util::download_file(&client, download_url, &zip_file_path, window, "BepInEx").await?;
-                    // Extract zip
-                    println!("Extracting : {}", zip_file_path.display());
-                    window.emit("progress", format!("Extracting")).unwrap();
-                    let zip_file = std::fs::File::open(&zip_file_path).or(Err(format!("Cannot open Zip file")))?;
-                    let mut zip_reader = zip::read::ZipArchive::new(zip_file).or(Err(format!("Cannot read zip file")))?;
-                    zip_reader.extract(among_us_path).or(Err(format!("Cannot extract zip file")))?;
-                    tokio::fs::remove_file(zip_file_path).await.or(Err(format!("Could not remove zip file")))?;
-                    tokio::fs::create_dir_all(&plugins_path).await.or(Err(format!("Cannot create plugins folder")))?;
-
-                    window.emit("progress", format!("Installing {}", self.name)).unwrap();
+                    return Err(format!("BepInEx has to be installed for {}", self.name));
                 }
                 // Copy first file from mod folder to plugins path
-                let dll_path = mod_folder.read_dir().unwrap().next().unwrap().unwrap().path();
-                tokio::fs::copy(
-                    &dll_path,
-                    plugins_path.join(Path::new(dll_path.file_name().unwrap()))
-                ).await.unwrap();
+                let dll_path = self.get_dll_path(config)?;
+                let assembly_info = self.get_assembly_info(config).await.map_err(|e| format!("{} was not installed: {}", self.name, e))?;
+                if !assembly_info.references.iter().any(|r| r.name.starts_with("BepInEx")) {
+                    log_info!("{} ({}) does not reference BepInEx and might not be a plugin", self.name, assembly_info.name);
+                }
+                // The dll is always the one of the mod, so it's replaced no matter the policy
+                let output_path = plugins_path.join(Path::new(dll_path.file_name().unwrap()));
+                let (installed_path, new_path) = (output_path.clone(), dll_path.clone());
+                let is_replaced = sync::run_blocking(move || {
+                    Ok(installed_path.exists() && sync::hash_file_cached(&installed_path)? != sync::hash_file_cached(&new_path)?)
+                }).await?;
+                if is_replaced {
+                    report.overwritten.push(Path::new("BepInEx/plugins").join(dll_path.file_name().unwrap()));
+                }
+                tokio::fs::copy(&dll_path, &output_path).await
+                    .map_err(|e| format!("Cannot copy {} to {}: {}", dll_path.display(), output_path.display(), e))?;
             }
         }
-        Ok(())
+        for path in &report.overwritten {
+            log_info!("{} overwrote {}", self.name, path.display());
+        }
+        self.plugin_guids = self.read_plugin_infos(config).into_iter().map(|p| p.guid).collect();
+        if let Err(e) = self.restore_configs(config).await {
+            log_error!("Cannot restore the configs of {}: {}", self.name, e);
+        }
+        Ok(report)
+    }
+
+    fn get_staging_folder(&self) -> Result<PathBuf, String> {
+        archive::check_name(&self.name).map_err(|e| format!("Invalid mod name: {}", e))?;
+        Ok(Path::new(STAGING_PATH).join(&self.name))
+    }
+
+    /// The game files the mod overwrote, saved with their paths relative to the game folder
+    pub fn get_originals_folder(&self) -> Result<PathBuf, String> {
+        archive::check_name(&self.name).map_err(|e| format!("Invalid mod name: {}", e))?;
+        Ok(Path::new(ORIGINALS_PATH).join(&self.name))
+    }
+
+    /// Puts the game files back that were overwritten by the mod
+    async fn restore_originals(&self, config : &Config) -> Result<(), String> {
+        let originals_folder = self.get_originals_folder()?;
+        let among_us_path = PathBuf::from(&config.among_us_path);
+        let name = self.name.clone();
+        sync::run_blocking(move || put_back_originals(&originals_folder, &among_us_path, &name)).await
     }
 
     pub async fn uninstall(&mut self, config : &Config) -> Result<(), String> {
         if !self.do_uninstall || self.enabled { return Ok(()) }
-        let mod_folder = self.get_mod_folder(config);
-        if !mod_folder.exists() { return Ok(()) }
-        let plugins_path = util::get_plugins_path(config);
-        let remove_paths : Vec<PathBuf> = match self.mod_type {
-            ModType::Files => {
-                let plugins_folder = mod_folder.join(Path::new("BepInEx/plugins"));
-                plugins_folder
-                    .read_dir()
-                    .or(Err(format!("{} has not plugins folder", self.name)))?
-                    .map(|file| {
-                        let path = file.unwrap().path();
-                        let name = path.file_name().unwrap();
-                        plugins_path.join(name)
-                    }).collect()
-            },
-            ModType::Dll => {
-                let dll_path = mod_folder.read_dir().unwrap().next().unwrap().unwrap().path();
-                let name = dll_path.file_name().unwrap();
-                vec![plugins_path.join(name)]
-            }
-        };
-        for remove_path in remove_paths {
-            if remove_path.exists() {
-                tokio::fs::remove_file(remove_path)
-                    .await
-                    .or(Err(format!("Cannot uninstall {}", self.name)))?
-            }
-        }
+        self.snapshot_configs(config).await?;
+        self.remove_installed_files(config).await?;
+        self.restore_originals(config).await?;
+        self.installed_files = Some(vec![]);
         self.do_uninstall = false;
         Ok(())
     }
 
+    /// Removes every file the mod put into the game folder and the folders that are left empty by that
+    async fn remove_installed_files(&self, config : &Config) -> Result<(), String> {
+        let among_us_path = PathBuf::from(&config.among_us_path);
+        let installed_files = self.get_installed_files(config);
+        let name = self.name.clone();
+        sync::run_blocking(move || remove_files(&among_us_path, &installed_files, &name)).await
+    }
+
+    /// Downloads the mod again and replaces the installed files with the ones of the new download.
+    /// Used by the watch mode to deploy a rebuilt local mod.
+    pub async fn redeploy(&mut self, config : &Config, window : &Window) -> Result<(), String> {
+        let mod_folder = self.get_mod_folder(config)?;
+        // The new build is downloaded somewhere else first, so a failed download leaves the installed mod as it is
+        let staging_folder = self.get_staging_folder()?;
+        if staging_folder.exists() {
+            tokio::fs::remove_dir_all(&staging_folder).await.or(Err(format!("Cannot remove {}", staging_folder.display())))?;
+        }
+        tokio::fs::create_dir_all(&staging_folder).await.or(Err(format!("Cannot create {}", staging_folder.display())))?;
+        if let Err(e) = self.fetch(&staging_folder, window).await {
+            let _ = tokio::fs::remove_dir_all(&staging_folder).await;
+            return Err(e);
+        }
+        self.snapshot_configs(config).await?;
+        self.remove_installed_files(config).await?;
+        if mod_folder.exists() {
+            tokio::fs::remove_dir_all(&mod_folder).await.or(Err(format!("Could not remove mod folder")))?;
+        }
+        if tokio::fs::rename(&staging_folder, &mod_folder).await.is_err() {
+            // The mods folder can be on another drive, where the build has to be copied instead
+            let plan = sync::plan_sync(&staging_folder, &mod_folder, sync::ExistingFiles::ReplaceChanged).await?;
+            sync::run_sync(plan, &self.name, window).await?;
+            tokio::fs::remove_dir_all(&staging_folder).await.or(Err(format!("Cannot remove {}", staging_folder.display())))?;
+        }
+        // Files of the previous build that were not removed above have to be replaced, if the new build changed them
+        let install_policy = match self.install_policy {
+            InstallPolicy::Skip => InstallPolicy::BackupAndOverwrite,
+            install_policy => install_policy
+        };
+        self.install_files(config, window, install_policy).await?;
+        Ok(())
+    }
+
     pub async fn remove(&mut self, config : &Config) -> Result<(), String> {
         self.do_uninstall = true;
         self.enabled = false;
         self.uninstall(config).await?;
-        let mod_folder = self.get_mod_folder(config);
+        let mod_folder = self.get_mod_folder(config)?;
         if mod_folder.exists() {
             tokio::fs::remove_dir_all(mod_folder).await.or(Err(format!("Could not remove mod folder")))?;
         }
@@ -268,12 +515,93 @@ impl Mod {
     }
 }
 
-fn get_mod_type(asset: &Value) -> Result<ModType, String> {
-    Ok(match (asset.get("content_type").unwrap().as_str().unwrap(), asset.get("name").unwrap().as_str().unwrap().split(".").last().unwrap()) {
-        ("application/x-msdownload", _) => ModType::Dll,
-        ("application/octet-stream", "dll") => ModType::Dll,
-        ("application/x-zip-compressed", _) => ModType::Files,
-        ("application/zip", _) => ModType::Files,
-        (t, _) => { return Err(format!("Invalid mod file type : {}", t)) }
-    })
-}
\ No newline at end of file
+/// Which of the files that a mod without a record left alone are its own: the ones that aren't part of the vanilla game.
+/// Without a backup there is no telling, so all of them are treated as the files of the mod, like before they were recorded.
+fn adopt_kept_files(among_us_path : &Path, kept_files : Vec<PathBuf>, vanilla_manifest : Option<&BackupManifest>) -> Vec<PathBuf> {
+    kept_files.into_iter()
+        .filter(|p| !vanilla_manifest.map(|m| backup::is_vanilla_file(m, p, &among_us_path.join(p))).unwrap_or(false))
+        .collect()
+}
+
+/// Saves the files that are about to be overwritten and returns the ones that were saved.
+/// An original that was saved by an earlier install is kept, because the file might have come from the mod since then.
+/// If the vanilla manifest is given, only files of the vanilla game are saved.
+fn save_originals(originals_folder : &Path, replaced_files : &[(PathBuf, PathBuf)], vanilla_manifest : Option<&BackupManifest>) -> Result<Vec<PathBuf>, String> {
+    let mut saved = vec![];
+    for (relative_path, path) in replaced_files {
+        if vanilla_manifest.map(|m| !backup::is_vanilla_file(m, relative_path, path)).unwrap_or(false) { continue; }
+        let original_path = archive::safe_join(originals_folder, &relative_path.to_string_lossy())?;
+        if original_path.exists() { continue; }
+        std::fs::create_dir_all(original_path.parent().unwrap()).or(Err(format!("Cannot create {}", originals_folder.display())))?;
+        std::fs::copy(path, &original_path).or(Err(format!("Cannot save the original {}", path.display())))?;
+        saved.push(relative_path.clone());
+    }
+    Ok(saved)
+}
+
+fn save_configs(config_paths : &[PathBuf], snapshot_folder : &Path) -> Result<(), String> {
+    std::fs::create_dir_all(snapshot_folder).or(Err(format!("Cannot create {}", snapshot_folder.display())))?;
+    for config_path in config_paths {
+        let snapshot_path = snapshot_folder.join(config_path.file_name().unwrap());
+        std::fs::copy(config_path, &snapshot_path).or(Err(format!("Cannot save config {}", config_path.display())))?;
+    }
+    Ok(())
+}
+
+/// Puts saved config files back. If the mod brought its own config file, the old values are merged into it,
+/// so settings that were added or removed in the new version are handled.
+/// The snapshot is removed afterwards, so it can't overwrite changes made later.
+fn restore_saved_configs(snapshot_folder : &Path, config_folder : &Path) -> Result<(), String> {
+    let Ok(read_dir) = snapshot_folder.read_dir() else { return Ok(()) };
+    std::fs::create_dir_all(config_folder).or(Err(format!("Cannot create {}", config_folder.display())))?;
+    for entry in read_dir.filter_map(|e| e.ok()) {
+        let snapshot_path = entry.path();
+        let config_path = config_folder.join(entry.file_name());
+        if config_path.is_file() {
+            let old_config = ConfigFile::read(&snapshot_path)?;
+            let mut new_config = ConfigFile::read(&config_path)?;
+            let kept = new_config.merge_values(&old_config);
+            new_config.write(&config_path)?;
+            log_info!("Kept {} values in {}", kept, config_path.display());
+        } else {
+            std::fs::copy(&snapshot_path, &config_path).or(Err(format!("Cannot restore config {}", config_path.display())))?;
+        }
+    }
+    std::fs::remove_dir_all(snapshot_folder).or(Err(format!("Cannot remove {}", snapshot_folder.display())))
+}
+
+fn put_back_originals(originals_folder : &Path, among_us_path : &Path, name : &str) -> Result<(), String> {
+    if !originals_folder.exists() { return Ok(()) }
+    for entry in WalkDir::new(originals_folder).into_iter().filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()) {
+        let relative_path = pathdiff::diff_paths(entry.path(), originals_folder).unwrap();
+        let game_path = archive::safe_join(among_us_path, &relative_path.to_string_lossy())?;
+        if let Some(parent_dir) = game_path.parent() {
+            std::fs::create_dir_all(parent_dir).or(Err(format!("Cannot create {}", parent_dir.display())))?;
+        }
+        std::fs::copy(entry.path(), &game_path).or(Err(format!("Cannot restore {}", game_path.display())))?;
+        log_info!("Restored {} after uninstalling {}", relative_path.display(), name);
+    }
+    std::fs::remove_dir_all(originals_folder).or(Err(format!("Cannot remove {}", originals_folder.display())))
+}
+
+/// Removes files from the game folder. Folders that are left empty are removed too, except for the ones BepInEx needs.
+fn remove_files(among_us_path : &Path, relative_paths : &[PathBuf], name : &str) -> Result<(), String> {
+    const KEPT_FOLDERS : [&'static str; 3] = ["BepInEx", "BepInEx/plugins", "BepInEx/config"];
+    for relative_path in relative_paths {
+        let path = archive::safe_join(among_us_path, &relative_path.to_string_lossy())?;
+        let result = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else if path.is_file() {
+            std::fs::remove_file(&path)
+        } else {
+            continue;
+        };
+        result.or(Err(format!("Cannot uninstall {}, {} could not be removed", name, path.display())))?;
+        for folder in relative_path.ancestors().skip(1) {
+            if folder.as_os_str().is_empty() || KEPT_FOLDERS.iter().any(|k| folder == Path::new(k)) { break; }
+            // Fails for folders that still contain something, which is fine
+            if std::fs::remove_dir(among_us_path.join(folder)).is_err() { break; }
+        }
+    }
+    Ok(())
+}
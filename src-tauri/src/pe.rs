@@ -0,0 +1,150 @@
+pub const IMAGE_DIRECTORY_ENTRY_RESOURCE : usize = 2;
+pub const IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR : usize = 14;
+
+pub fn read_u16(data : &[u8], offset : usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+pub fn read_u32(data : &[u8], offset : usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DataDirectory {
+    pub rva : u32,
+    pub size : u32
+}
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub virtual_address : u32,
+    pub virtual_size : u32,
+    pub raw_data_offset : u32,
+    pub raw_data_size : u32
+}
+
+pub struct PeFile<'a> {
+    pub data : &'a [u8],
+    pub data_directories : Vec<DataDirectory>,
+    pub sections : Vec<Section>
+}
+
+impl<'a> PeFile<'a> {
+    pub fn parse(data : &'a [u8]) -> Result<Self, String> {
+        if !data.starts_with(b"MZ") {
+            return Err("Not a PE file (missing MZ header)".to_string());
+        }
+        let pe_offset = read_u32(data, 0x3C).ok_or("Truncated DOS header")? as usize;
+        if data.get(pe_offset..pe_offset + 4) != Some(b"PE\0\0") {
+            return Err("Not a PE file (missing PE signature)".to_string());
+        }
+        let coff_offset = pe_offset + 4;
+        let section_count = read_u16(data, coff_offset + 2).ok_or("Truncated COFF header")? as usize;
+        let optional_header_size = read_u16(data, coff_offset + 16).ok_or("Truncated COFF header")? as usize;
+        let optional_header_offset = coff_offset + 20;
+        // The data directories start at a different offset for 32 and 64 bit files
+        let data_directories_offset = match read_u16(data, optional_header_offset).ok_or("Truncated optional header")? {
+            0x10B => optional_header_offset + 96,
+            0x20B => optional_header_offset + 112,
+            magic => return Err(format!("Unknown optional header magic {:#x}", magic))
+        };
+        let data_directory_count = read_u32(data, data_directories_offset - 4).ok_or("Truncated optional header")? as usize;
+        let mut data_directories = Vec::with_capacity(data_directory_count.min(16));
+        for i in 0..data_directory_count.min(16) {
+            let offset = data_directories_offset + i * 8;
+            if offset + 8 > optional_header_offset + optional_header_size { break; }
+            data_directories.push(DataDirectory {
+                rva: read_u32(data, offset).ok_or("Truncated data directories")?,
+                size: read_u32(data, offset + 4).ok_or("Truncated data directories")?
+            });
+        }
+        let section_table_offset = optional_header_offset + optional_header_size;
+        let mut sections = Vec::with_capacity(section_count);
+        for i in 0..section_count {
+            let offset = section_table_offset + i * 40;
+            sections.push(Section {
+                virtual_size: read_u32(data, offset + 8).ok_or("Truncated section table")?,
+                virtual_address: read_u32(data, offset + 12).ok_or("Truncated section table")?,
+                raw_data_size: read_u32(data, offset + 16).ok_or("Truncated section table")?,
+                raw_data_offset: read_u32(data, offset + 20).ok_or("Truncated section table")?
+            });
+        }
+        Ok(Self { data, data_directories, sections })
+    }
+
+    /// Converts a relative virtual address into an offset in the file
+    pub fn rva_to_offset(&self, rva : u32) -> Option<usize> {
+        self.sections.iter().find(|s| {
+            let size = s.virtual_size.max(s.raw_data_size);
+            rva >= s.virtual_address && rva < s.virtual_address.saturating_add(size)
+        }).and_then(|s| {
+            let offset = rva - s.virtual_address;
+            if offset >= s.raw_data_size { return None; }
+            // Crafted files can point past the end of the address space
+            s.raw_data_offset.checked_add(offset).map(|o| o as usize)
+        })
+    }
+
+    /// Returns the data a data directory points to, if it exists
+    pub fn get_directory_data(&self, index : usize) -> Option<&'a [u8]> {
+        let directory = self.data_directories.get(index)?;
+        if directory.rva == 0 || directory.size == 0 { return None; }
+        let offset = self.rva_to_offset(directory.rva)?;
+        self.data.get(offset..offset.checked_add(directory.size as usize)?)
+    }
+
+    /// Managed (.NET) assemblies have a CLI header, native dlls don't.
+    /// Only its data directory is checked, so the headers of the file are enough.
+    pub fn has_cli_header(&self) -> bool {
+        self.data_directories.get(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR).map(|d| d.rva != 0 && d.size != 0).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Reads a dll written by `test_data/make_dlls.py`
+    pub fn read_fixture(name : &str) -> Vec<u8> {
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data").join("dlls").join(name)).unwrap()
+    }
+
+    #[test]
+    fn tells_managed_and_native_dlls_apart() {
+        let managed = read_fixture("managed.dll");
+        let native = read_fixture("native.dll");
+        let managed_file = PeFile::parse(&managed).unwrap();
+        let native_file = PeFile::parse(&native).unwrap();
+        assert!(managed_file.has_cli_header());
+        assert!(!native_file.has_cli_header());
+        assert_eq!((managed_file.data_directories.len(), managed_file.sections.len()), (16, 2));
+        assert_eq!((native_file.data_directories.len(), native_file.sections.len()), (16, 2));
+        // The CLI header is at the start of the first section
+        assert_eq!(managed_file.get_directory_data(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR).map(|d| read_u32(d, 0)), Some(Some(72)));
+        assert!(native_file.get_directory_data(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR).is_none());
+    }
+
+    #[test]
+    fn converts_addresses_inside_sections_only() {
+        let native = read_fixture("native.dll");
+        let pe_file = PeFile::parse(&native).unwrap();
+        let section = &pe_file.sections[1];
+        assert_eq!(pe_file.rva_to_offset(section.virtual_address + 4), Some(section.raw_data_offset as usize + 4));
+        assert_eq!(pe_file.rva_to_offset(0), None);
+        assert_eq!(pe_file.rva_to_offset(u32::MAX), None);
+    }
+
+    #[test]
+    fn rejects_truncated_or_foreign_files() {
+        let managed = read_fixture("managed.dll");
+        let cut_before = |text : &[u8]| managed.windows(text.len()).position(|w| w == text).unwrap();
+        assert_eq!(PeFile::parse(&managed[..0x30]).err().unwrap(), "Truncated DOS header");
+        assert_eq!(PeFile::parse(&managed[..0x82]).err().unwrap(), "Not a PE file (missing PE signature)");
+        assert_eq!(PeFile::parse(&managed[..0x90]).err().unwrap(), "Truncated COFF header");
+        assert_eq!(PeFile::parse(&managed[..cut_before(b".rsrc")]).err().unwrap(), "Truncated section table");
+        assert_eq!(PeFile::parse(b"PK\x03\x04").err().unwrap(), "Not a PE file (missing MZ header)");
+        // The headers are enough to tell a managed dll, even if the rest of the file is missing
+        assert!(PeFile::parse(&managed[..0x200]).unwrap().has_cli_header());
+    }
+}
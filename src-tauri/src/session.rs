@@ -0,0 +1,190 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use sysinfo::{Pid, PidExt, ProcessExt, System, SystemExt};
+use tauri::Window;
+use tokio::process::Child;
+use tokio::task::JoinHandle;
+use crate::{backup, logs, sync, util, GlobalConfig};
+
+const POLL_INTERVAL_MS : u64 = 1000;
+const RUNNING_EVENT_INTERVAL_SECS : u64 = 10;
+/// How long to wait for the game process to show up, when it is started by a launcher
+const PROCESS_SEARCH_TIMEOUT_SECS : u64 = 120;
+const MAX_SESSION_HISTORY : usize = 100;
+
+/// Set from the moment the game is launched until its process exited
+static SESSION_ACTIVE : AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status")]
+pub enum SessionEvent {
+    Started { pid : u32 },
+    Running { pid : u32, duration_secs : u64 },
+    /// The exit code is only known if the game was started as a child process
+    Exited { exit_code : Option<i32>, duration_secs : u64, suspected_mod : Option<String> },
+    /// The launcher was opened, but the game process never showed up
+    NotFound,
+    /// The mods of a temporary session were removed again
+    Reverted,
+    RevertFailed { error : String },
+    /// The game of a temporary session was not found, so it might still start. The mods stay installed.
+    RevertSkipped
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRecord {
+    /// Unix timestamp in seconds
+    pub started_at : u64,
+    pub duration_secs : u64,
+    pub exit_code : Option<i32>,
+    /// The mod that caused the last error, if the game did not exit cleanly
+    #[serde(default)]
+    pub suspected_mod : Option<String>
+}
+
+fn emit_session_event(window : &Window, event : SessionEvent) {
+    window.emit("session", event).unwrap();
+}
+
+fn find_among_us_process(sys : &mut System) -> Option<u32> {
+    sys.refresh_processes();
+    sys.processes_by_name("Among Us").next().map(|p| p.pid().as_u32())
+}
+
+/// Waits for a process, that was not started by us, to exit by polling it
+async fn wait_for_process(pid : u32, started : Instant, window : &Window) {
+    let mut sys = System::new();
+    let mut last_running_event = Instant::now();
+    while sys.refresh_process(Pid::from_u32(pid)) {
+        if last_running_event.elapsed().as_secs() >= RUNNING_EVENT_INTERVAL_SECS {
+            last_running_event = Instant::now();
+            emit_session_event(window, SessionEvent::Running { pid, duration_secs: started.elapsed().as_secs() });
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(POLL_INTERVAL_MS)).await;
+    }
+}
+
+/// Waits for a child process to exit and returns its exit code
+async fn wait_for_child(mut child : Child, started : Instant, window : &Window) -> Option<i32> {
+    let pid = child.id().unwrap_or(0);
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(RUNNING_EVENT_INTERVAL_SECS));
+    // The first tick completes immediately
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            status = child.wait() => {
+                return match status {
+                    Ok(status) => status.code(),
+                    Err(e) => {
+                        log_error!("Cannot wait for Among Us: {}", e);
+                        None
+                    }
+                };
+            },
+            _ = interval.tick() => {
+                emit_session_event(window, SessionEvent::Running { pid, duration_secs: started.elapsed().as_secs() });
+            }
+        }
+    }
+}
+
+async fn monitor(child : Option<Child>, config : GlobalConfig, window : Window) -> Option<SessionRecord> {
+    let started = Instant::now();
+    let started_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let log_tail = logs::start_tailing(&*config.lock().await, window.clone()).await;
+    let exit_code = match child {
+        Some(child) => {
+            let pid = child.id().unwrap_or(0);
+            emit_session_event(&window, SessionEvent::Started { pid });
+            wait_for_child(child, started, &window).await
+        },
+        None => {
+            // The game was started through a launcher, so its process has to be found first
+            let mut sys = System::new();
+            let pid = loop {
+                if let Some(pid) = find_among_us_process(&mut sys) { break Some(pid); }
+                if started.elapsed().as_secs() >= PROCESS_SEARCH_TIMEOUT_SECS { break None; }
+                tokio::time::sleep(tokio::time::Duration::from_millis(POLL_INTERVAL_MS)).await;
+            };
+            let Some(pid) = pid else {
+                SESSION_ACTIVE.store(false, Ordering::SeqCst);
+                log_tail.abort();
+                emit_session_event(&window, SessionEvent::NotFound);
+                return None;
+            };
+            emit_session_event(&window, SessionEvent::Started { pid });
+            wait_for_process(pid, started, &window).await;
+            None
+        }
+    };
+    let duration_secs = started.elapsed().as_secs();
+    logs::stop_tailing(log_tail).await;
+    SESSION_ACTIVE.store(false, Ordering::SeqCst);
+    let suspected_mod = if exit_code != Some(0) { logs::get_last_error_mod().await } else { None };
+    let record = SessionRecord { started_at, duration_secs, exit_code, suspected_mod: suspected_mod.clone() };
+    log_info!("Among Us exited after {}s with exit code {:?}", duration_secs, exit_code);
+    if let Some(suspected_mod) = &suspected_mod {
+        log_info!("The last error was caused by {}", suspected_mod);
+    }
+    emit_session_event(&window, SessionEvent::Exited { exit_code, duration_secs, suspected_mod });
+    let mut config = config.lock().await;
+    config.session_history.push(record.clone());
+    let overflow = config.session_history.len().saturating_sub(MAX_SESSION_HISTORY);
+    config.session_history.drain(..overflow);
+    config.save();
+    Some(record)
+}
+
+/// Starts tracking a game that was just launched. `child` is the game process, if it was started by us.
+/// The returned handle completes with the session, once the game has exited.
+pub fn start(child : Option<Child>, config : GlobalConfig, window : Window) -> JoinHandle<Option<SessionRecord>> {
+    // Mark the session as active right away, so nothing touches the game files while its process is starting
+    SESSION_ACTIVE.store(true, Ordering::SeqCst);
+    tokio::spawn(monitor(child, config, window))
+}
+
+/// Waits for a temporary session to end and then reverts the game folder to the vanilla backup
+pub fn revert_after_exit(session : JoinHandle<Option<SessionRecord>>, snapshot_path : PathBuf, config : GlobalConfig, window : Window) {
+    tokio::spawn(async move {
+        // A launcher can take longer than the search for the game, reverting then would pull the mods from under it
+        if !matches!(session.await, Ok(Some(_))) {
+            log_info!("Not reverting Among Us, because the game was not found");
+            emit_session_event(&window, SessionEvent::RevertSkipped);
+            return;
+        }
+        let mut config = config.lock().await;
+        // The vanilla game has no config folder, so the mod settings are saved until the mods are installed again
+        for modification in &config.downloaded {
+            if let Err(e) = modification.snapshot_configs(&config).await {
+                log_error!("Cannot save the configs of {}: {}", modification.name, e);
+            }
+        }
+        log_info!("Reverting Among Us to the vanilla backup");
+        let among_us_path = PathBuf::from(&config.among_us_path);
+        match sync::run_blocking(move || backup::restore(&among_us_path, &snapshot_path, None)).await {
+            Ok(_) => {
+                config.forget_installed_files();
+                config.save();
+                emit_session_event(&window, SessionEvent::Reverted)
+            },
+            Err(error) => {
+                log_error!("Cannot revert Among Us: {}", error);
+                emit_session_event(&window, SessionEvent::RevertFailed { error })
+            }
+        }
+    });
+}
+
+pub fn is_game_running() -> bool {
+    SESSION_ACTIVE.load(Ordering::SeqCst) || util::is_among_us_running()
+}
+
+/// Files under `BepInEx/plugins` are locked while the game runs, so mod operations have to wait until it exited
+pub fn ensure_game_not_running() -> Result<(), String> {
+    if is_game_running() {
+        return Err("Among Us is running, close it before changing mods".to_string());
+    }
+    Ok(())
+}
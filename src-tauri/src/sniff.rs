@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use serde_json::Value;
+use crate::archive::ArchiveFormat;
+use crate::mod_manager::ModType;
+use crate::pe::PeFile;
+use crate::sync;
+
+const ARCHIVE_EXTENSIONS : [&'static str; 5] = ["zip", "7z", "gz", "tgz", "rar"];
+/// Enough for the magic bytes of archives and the headers of PE files
+const HEADER_SIZE : u64 = 4096;
+
+/// Classifies a file by the start of its content: .NET assemblies are dll mods and archives are file mods
+pub fn classify_bytes(data : &[u8]) -> Option<ModType> {
+    if ArchiveFormat::from_magic_bytes(data).is_some() {
+        return Some(ModType::Files);
+    }
+    match PeFile::parse(data) {
+        Ok(pe_file) if pe_file.has_cli_header() => Some(ModType::Dll),
+        _ => None
+    }
+}
+
+/// Fallback for files, whose content is not recognized
+pub fn classify_file_name(name : &str) -> Option<ModType> {
+    let extension = name.rsplit('.').next()?.to_lowercase();
+    if extension == "dll" {
+        Some(ModType::Dll)
+    } else if ARCHIVE_EXTENSIONS.contains(&extension.as_str()) {
+        Some(ModType::Files)
+    } else {
+        None
+    }
+}
+
+fn read_header(path : &Path) -> Result<Vec<u8>, String> {
+    let file = File::open(path).or(Err(format!("Cannot read {}", path.display())))?;
+    let mut header = vec![];
+    file.take(HEADER_SIZE).read_to_end(&mut header).or(Err(format!("Cannot read {}", path.display())))?;
+    Ok(header)
+}
+
+/// Classifies a downloaded or local mod file by its magic bytes, falling back to the extension
+pub async fn classify_file(path : &Path) -> Result<ModType, String> {
+    let header_path = path.to_path_buf();
+    let header = sync::run_blocking(move || read_header(&header_path)).await?;
+    classify_bytes(&header)
+        .or_else(|| classify_file_name(&path.file_name()?.to_string_lossy()))
+        .ok_or(format!("{} is neither a dll nor a supported archive", path.display()))
+}
+
+/// Guesses the type of a GitHub release asset before it is downloaded.
+/// The upload metadata is unreliable, so the file name is checked first and the content type is only used if that does not help.
+/// The guess gets replaced by [`classify_file`] once the asset is downloaded.
+pub fn classify_asset(asset : &Value) -> Option<ModType> {
+    let name = asset.get("name").and_then(|n| n.as_str()).unwrap_or("");
+    if let Some(mod_type) = classify_file_name(name) {
+        return Some(mod_type);
+    }
+    match asset.get("content_type").and_then(|t| t.as_str()).unwrap_or("") {
+        "application/x-msdownload" | "application/x-dosexec" | "application/vnd.microsoft.portable-executable" => Some(ModType::Dll),
+        "application/zip" | "application/x-zip" | "application/x-zip-compressed" => Some(ModType::Files),
+        "application/x-7z-compressed" => Some(ModType::Files),
+        "application/gzip" | "application/x-gzip" | "application/x-gtar" | "application/x-compressed-tar" => Some(ModType::Files),
+        "application/vnd.rar" | "application/x-rar-compressed" => Some(ModType::Files),
+        _ => None
+    }
+}
+
+/// Whether a release asset might be a mod. Assets with a generic content type are kept even if their name doesn't tell,
+/// because their content is checked once they are downloaded.
+pub fn is_candidate_asset(asset : &Value) -> bool {
+    classify_asset(asset).is_some() || asset.get("content_type").and_then(|t| t.as_str()) == Some("application/octet-stream")
+}
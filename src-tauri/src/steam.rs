@@ -0,0 +1,327 @@
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::AMONG_US_STEAM_ID;
+
+/// Value in Valve's KeyValues (VDF) text format, used by `libraryfolders.vdf` and `appmanifest_*.acf`
+#[derive(Debug, Clone)]
+pub enum VdfValue {
+    String(String),
+    Object(Vec<(String, VdfValue)>)
+}
+
+impl VdfValue {
+    /// Keys are case insensitive, because Steam is not consistent about them
+    pub fn get(&self, key : &str) -> Option<&VdfValue> {
+        match self {
+            VdfValue::Object(entries) => entries.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v),
+            VdfValue::String(_) => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            VdfValue::String(s) => Some(s),
+            VdfValue::Object(_) => None
+        }
+    }
+
+    pub fn entries(&self) -> &[(String, VdfValue)] {
+        match self {
+            VdfValue::Object(entries) => entries,
+            VdfValue::String(_) => &[]
+        }
+    }
+
+    pub fn parse(text : &str) -> Result<Self, String> {
+        let tokens = tokenize_vdf(text)?;
+        let mut position = 0;
+        let entries = parse_vdf_entries(&tokens, &mut position)?;
+        if position < tokens.len() {
+            return Err("Unexpected } in VDF file".to_string());
+        }
+        Ok(VdfValue::Object(entries))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum VdfToken {
+    String(String),
+    Open,
+    Close
+}
+
+fn tokenize_vdf(text : &str) -> Result<Vec<VdfToken>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(VdfToken::Open),
+            '}' => tokens.push(VdfToken::Close),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some(escaped) => string.push(escaped),
+                            None => return Err("Unterminated string in VDF file".to_string())
+                        },
+                        Some(c) => string.push(c),
+                        None => return Err("Unterminated string in VDF file".to_string())
+                    }
+                }
+                tokens.push(VdfToken::String(string));
+            },
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            },
+            // Conditionals like [$WIN32] are ignored
+            '[' => {
+                while chars.next_if(|c| *c != ']').is_some() {}
+                chars.next();
+            },
+            c if c.is_whitespace() => {},
+            c => {
+                let mut string = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !matches!(c, '{' | '}' | '"')) {
+                    string.push(c);
+                }
+                tokens.push(VdfToken::String(string));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_vdf_entries(tokens : &[VdfToken], position : &mut usize) -> Result<Vec<(String, VdfValue)>, String> {
+    let mut entries = Vec::new();
+    while let Some(token) = tokens.get(*position) {
+        let key = match token {
+            VdfToken::String(key) => key.clone(),
+            VdfToken::Close => break,
+            VdfToken::Open => return Err("Unexpected { in VDF file".to_string())
+        };
+        *position += 1;
+        let value = match tokens.get(*position) {
+            Some(VdfToken::String(value)) => {
+                *position += 1;
+                VdfValue::String(value.clone())
+            },
+            Some(VdfToken::Open) => {
+                *position += 1;
+                let children = parse_vdf_entries(tokens, position)?;
+                if tokens.get(*position) != Some(&VdfToken::Close) {
+                    return Err(format!("Missing }} after {} in VDF file", key));
+                }
+                *position += 1;
+                VdfValue::Object(children)
+            },
+            _ => return Err(format!("Missing value for {} in VDF file", key))
+        };
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+/// Where and how Among Us is installed through Steam
+#[derive(Debug, Clone, Serialize)]
+pub struct SteamInstall {
+    pub steam_root : PathBuf,
+    pub library_path : PathBuf,
+    pub install_path : PathBuf,
+    pub build_id : Option<String>,
+    /// On Linux the Windows build of the game is run through Proton, which keeps its Wine prefix here
+    pub proton_prefix : Option<PathBuf>
+}
+
+#[cfg(target_family = "windows")]
+fn get_steam_root_candidates() -> Vec<PathBuf> {
+    use winreg::{enums::HKEY_CURRENT_USER, RegKey};
+    let mut candidates = vec![];
+    if let Ok(steam_key) = RegKey::predef(HKEY_CURRENT_USER).open_subkey("Software\\Valve\\Steam") {
+        if let Ok(steam_path) = steam_key.get_value::<String, _>("SteamPath") {
+            candidates.push(PathBuf::from(steam_path));
+        }
+    }
+    candidates.push(PathBuf::from("C:\\Program Files (x86)\\Steam"));
+    candidates.push(PathBuf::from("C:\\Program Files\\Steam"));
+    candidates
+}
+
+#[cfg(target_family = "unix")]
+fn get_steam_root_candidates() -> Vec<PathBuf> {
+    let Some(home) = std::env::var_os("HOME").map(PathBuf::from) else { return vec![]; };
+    vec![
+        home.join(".steam/steam"),
+        home.join(".steam/root"),
+        home.join(".local/share/Steam"),
+        // Flatpak
+        home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+        // macOS
+        home.join("Library/Application Support/Steam")
+    ]
+}
+
+pub fn find_steam_root() -> Option<PathBuf> {
+    get_steam_root_candidates().into_iter().find(|p| p.join("steamapps").is_dir())
+}
+
+/// Reads all library folders from `libraryfolders.vdf`. The Steam root itself is always a library.
+pub fn get_library_folders(steam_root : &Path) -> Vec<PathBuf> {
+    let mut libraries = vec![steam_root.to_path_buf()];
+    let library_folders_path = steam_root.join("steamapps").join("libraryfolders.vdf");
+    let Ok(text) = std::fs::read_to_string(&library_folders_path) else { return libraries; };
+    let vdf = match VdfValue::parse(&text) {
+        Ok(vdf) => vdf,
+        Err(e) => {
+            log_error!("Cannot parse {}: {}", library_folders_path.display(), e);
+            return libraries;
+        }
+    };
+    let Some(library_folders) = vdf.get("libraryfolders") else { return libraries; };
+    for (key, value) in library_folders.entries() {
+        if key.parse::<u32>().is_err() { continue; }
+        // Older Steam versions store the path directly, newer ones have an object with the path and the installed apps
+        let path = match value {
+            VdfValue::String(path) => Some(path.as_str()),
+            VdfValue::Object(_) => value.get("path").and_then(|p| p.as_str())
+        };
+        if let Some(path) = path.map(PathBuf::from) {
+            if !libraries.contains(&path) {
+                libraries.push(path);
+            }
+        }
+    }
+    libraries
+}
+
+/// Reads the install folder and build id from the app manifest of Among Us in a library
+fn read_app_manifest(library_path : &Path) -> Option<(PathBuf, Option<String>)> {
+    let manifest_path = library_path.join("steamapps").join(format!("appmanifest_{}.acf", AMONG_US_STEAM_ID));
+    let text = std::fs::read_to_string(&manifest_path).ok()?;
+    let vdf = VdfValue::parse(&text).map_err(|e| log_error!("Cannot parse {}: {}", manifest_path.display(), e)).ok()?;
+    let app_state = vdf.get("AppState")?;
+    let install_dir = app_state.get("installdir")?.as_str()?;
+    let build_id = app_state.get("buildid").and_then(|b| b.as_str()).map(|b| b.to_string());
+    Some((library_path.join("steamapps").join("common").join(install_dir), build_id))
+}
+
+pub fn find_among_us() -> Option<SteamInstall> {
+    let steam_root = find_steam_root()?;
+    for library_path in get_library_folders(&steam_root) {
+        let Some((install_path, build_id)) = read_app_manifest(&library_path) else { continue; };
+        if !install_path.join("Among Us.exe").is_file() { continue; }
+        let proton_prefix = library_path.join("steamapps").join("compatdata").join(AMONG_US_STEAM_ID).join("pfx");
+        return Some(SteamInstall {
+            steam_root,
+            library_path,
+            install_path,
+            build_id,
+            proton_prefix: if cfg!(target_family = "unix") && proton_prefix.is_dir() { Some(proton_prefix) } else { None }
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY_FOLDERS : &str = r#"
+"libraryfolders"
+{
+	"contentstatsid"		"-1234567890"
+	"0"
+	{
+		"path"		"C:\\Program Files (x86)\\Steam"
+		"label"		""
+		"apps"
+		{
+			"228980"		"123456"
+		}
+	}
+	// A second drive
+	"1"
+	{
+		"path"		"D:\\SteamLibrary"
+		"apps"
+		{
+			"945360"		"456789"
+		}
+	}
+}
+"#;
+
+    /// Layout of Steam versions before mid 2021
+    const OLD_LIBRARY_FOLDERS : &str = r#"
+"LibraryFolders"
+{
+	"TimeNextStatsReport"		"1600000000"
+	"ContentStatsID"		"-1234567890"
+	"1"		"D:\\SteamLibrary"
+	"2"		"/mnt/games/SteamLibrary"
+}
+"#;
+
+    fn write_steam_root(name : &str, library_folders : &str) -> PathBuf {
+        let steam_root = std::env::temp_dir().join(format!("sussy_steam_test_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(steam_root.join("steamapps")).unwrap();
+        std::fs::write(steam_root.join("steamapps").join("libraryfolders.vdf"), library_folders).unwrap();
+        steam_root
+    }
+
+    #[test]
+    fn parses_nested_objects_and_escapes() {
+        let vdf = VdfValue::parse(LIBRARY_FOLDERS).unwrap();
+        let library_folders = vdf.get("LibraryFolders").unwrap();
+        assert_eq!(library_folders.entries().len(), 3);
+        let library = library_folders.get("1").unwrap();
+        assert_eq!(library.get("path").and_then(|p| p.as_str()), Some("D:\\SteamLibrary"));
+        assert_eq!(library.get("apps").and_then(|a| a.get("945360")).and_then(|b| b.as_str()), Some("456789"));
+        assert_eq!(library_folders.get("0").and_then(|l| l.get("label")).and_then(|l| l.as_str()), Some(""));
+    }
+
+    #[test]
+    fn ignores_comments_and_conditionals() {
+        let vdf = VdfValue::parse("// comment\n\"key\" \"value\" [$WIN32]\n\"other\" { \"nested\" unquoted }").unwrap();
+        assert_eq!(vdf.get("key").and_then(|v| v.as_str()), Some("value"));
+        assert_eq!(vdf.get("other").and_then(|v| v.get("nested")).and_then(|v| v.as_str()), Some("unquoted"));
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        for text in ["\"key\" \"value", "\"key\" { \"nested\" \"value\"", "\"key\" }", "\"key\"", "{ \"key\" \"value\" }"] {
+            assert!(VdfValue::parse(text).is_err(), "{:?} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn reads_library_folders() {
+        let steam_root = write_steam_root("new", LIBRARY_FOLDERS);
+        let libraries = get_library_folders(&steam_root);
+        std::fs::remove_dir_all(&steam_root).unwrap();
+        assert_eq!(libraries, vec![
+            steam_root.clone(),
+            PathBuf::from("C:\\Program Files (x86)\\Steam"),
+            PathBuf::from("D:\\SteamLibrary")
+        ]);
+    }
+
+    #[test]
+    fn reads_library_folders_of_old_steam_versions() {
+        let steam_root = write_steam_root("old", OLD_LIBRARY_FOLDERS);
+        let libraries = get_library_folders(&steam_root);
+        std::fs::remove_dir_all(&steam_root).unwrap();
+        assert_eq!(libraries, vec![steam_root.clone(), PathBuf::from("D:\\SteamLibrary"), PathBuf::from("/mnt/games/SteamLibrary")]);
+    }
+
+    #[test]
+    fn falls_back_to_the_steam_root() {
+        let steam_root = write_steam_root("broken", "\"libraryfolders\" {");
+        let libraries = get_library_folders(&steam_root);
+        std::fs::remove_dir_all(&steam_root).unwrap();
+        assert_eq!(libraries, vec![steam_root]);
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::UNIX_EPOCH;
+use once_cell::sync::Lazy;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use tauri::Window;
+use tokio::sync::Semaphore;
+use walkdir::WalkDir;
+use crate::{archive, util};
+
+const HASH_CACHE_PATH : &'static str = "sussy_hash_cache.json";
+/// Copying more files at once doesn't get faster on most disks
+const MAX_PARALLEL_COPIES : usize = 4;
+const PROGRESS_INTERVAL_MS : u64 = 200;
+const COPY_BUFFER_SIZE : usize = 256 * 1024;
+
+static HASH_CACHE : Lazy<Mutex<HashCache>> = Lazy::new(|| Default::default());
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedHash {
+    size : u64,
+    /// Nanoseconds since the unix epoch
+    modified : u128,
+    sha256 : String
+}
+
+/// Hashes of files by their path. A hash is only used while the size and modification time of the file didn't change.
+#[derive(Default)]
+struct HashCache {
+    entries : HashMap<PathBuf, CachedHash>,
+    loaded : bool,
+    changed : bool
+}
+
+impl HashCache {
+    fn load(&mut self) {
+        if self.loaded { return; }
+        self.loaded = true;
+        let Ok(text) = std::fs::read_to_string(HASH_CACHE_PATH) else { return; };
+        match serde_json::from_str(&text) {
+            Ok(entries) => self.entries = entries,
+            Err(e) => log_error!("Cannot read the hash cache: {}", e)
+        }
+    }
+}
+
+/// What to do with files that already exist in the destination
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExistingFiles {
+    Skip,
+    /// Replaced if their content differs
+    ReplaceChanged
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncProgress {
+    pub label : String,
+    pub copied_bytes : u64,
+    pub total_bytes : u64,
+    pub copied_files : u64,
+    pub total_files : u64
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    pub copied_files : u64,
+    pub copied_bytes : u64,
+    pub unchanged_files : u64,
+    pub skipped_files : u64,
+    /// Files that existed in the destination and were overwritten, relative to it
+    pub replaced_files : Vec<PathBuf>
+}
+
+struct PlannedCopy {
+    from : PathBuf,
+    to : PathBuf,
+    relative_path : PathBuf,
+    size : u64,
+    /// Whether a different file is already at the destination
+    replaces : bool
+}
+
+/// The files a sync is going to copy, so callers can look at what would be overwritten before anything is copied
+pub struct SyncPlan {
+    copies : Vec<PlannedCopy>,
+    /// Files of the source that already exist in the destination and are left alone, relative to it
+    kept : Vec<PathBuf>,
+    report : SyncReport
+}
+
+impl SyncPlan {
+    /// Files that would be copied, relative to the destination
+    pub fn get_copied_files(&self) -> Vec<PathBuf> {
+        self.copies.iter().map(|c| c.relative_path.clone()).collect()
+    }
+
+    pub fn get_kept_files(&self) -> Vec<PathBuf> {
+        self.kept.clone()
+    }
+
+    /// Existing files that would be overwritten, as (path relative to the destination, full path)
+    pub fn get_replaced_files(&self) -> Vec<(PathBuf, PathBuf)> {
+        self.copies.iter().filter(|c| c.replaces).map(|c| (c.relative_path.clone(), c.to.clone())).collect()
+    }
+
+    /// Keeps existing files whose relative path matches, instead of overwriting them
+    pub fn keep_existing(&mut self, keep : impl Fn(&Path) -> bool) {
+        let (kept, copies) : (Vec<PlannedCopy>, Vec<PlannedCopy>) = std::mem::take(&mut self.copies).into_iter()
+            .partition(|c| c.replaces && keep(&c.relative_path));
+        self.copies = copies;
+        self.report.skipped_files += kept.len() as u64;
+        self.kept.extend(kept.into_iter().map(|c| c.relative_path));
+    }
+}
+
+fn get_cache_key(path : &Path) -> PathBuf {
+    std::env::current_dir().map(|d| d.join(path)).unwrap_or(path.to_path_buf())
+}
+
+fn get_file_stamp(path : &Path) -> Option<(u64, u128)> {
+    let metadata = path.metadata().ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    Some((metadata.len(), modified))
+}
+
+fn remember_hash(path : &Path, sha256 : &str) {
+    let Some((size, modified)) = get_file_stamp(path) else { return; };
+    let mut cache = HASH_CACHE.lock().unwrap();
+    cache.load();
+    cache.entries.insert(get_cache_key(path), CachedHash { size, modified, sha256: sha256.to_string() });
+    cache.changed = true;
+}
+
+/// Like `util::hash_file`, but files that didn't change since they were last hashed are not read again
+pub fn hash_file_cached(path : &Path) -> Result<String, String> {
+    let stamp = get_file_stamp(path);
+    if let Some((size, modified)) = stamp {
+        let mut cache = HASH_CACHE.lock().unwrap();
+        cache.load();
+        match cache.entries.get(&get_cache_key(path)) {
+            Some(cached) if cached.size == size && cached.modified == modified => return Ok(cached.sha256.clone()),
+            _ => {}
+        }
+    }
+    let sha256 = util::hash_file(path)?;
+    remember_hash(path, &sha256);
+    Ok(sha256)
+}
+
+/// Writes the hash cache to disk, if anything was added to it
+pub fn save_hash_cache() {
+    let mut cache = HASH_CACHE.lock().unwrap();
+    if !cache.changed { return; }
+    // Files that were removed don't need to be remembered
+    cache.entries.retain(|p, _| p.is_file());
+    match serde_json::to_string(&cache.entries) {
+        Ok(json) => match std::fs::write(HASH_CACHE_PATH, json) {
+            Ok(_) => cache.changed = false,
+            Err(e) => log_error!("Cannot write the hash cache: {}", e)
+        },
+        Err(e) => log_error!("Cannot write the hash cache: {}", e)
+    }
+}
+
+/// Runs blocking file system work on the blocking thread pool, so it doesn't stall the async runtime
+pub async fn run_blocking<T, F>(function : F) -> Result<T, String>
+    where T : Send + 'static, F : FnOnce() -> Result<T, String> + Send + 'static {
+    tokio::task::spawn_blocking(function).await.map_err(|e| format!("A background task failed: {}", e))?
+}
+
+fn is_same_file(from : &Path, to : &Path) -> bool {
+    if from.metadata().map(|m| m.len()).ok() != to.metadata().map(|m| m.len()).ok() { return false; }
+    match (hash_file_cached(from), hash_file_cached(to)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false
+    }
+}
+
+fn plan(from : &Path, to : &Path, existing_files : ExistingFiles) -> Result<SyncPlan, String> {
+    let mut copies = vec![];
+    let mut kept = vec![];
+    let mut report = SyncReport::default();
+    for entry in WalkDir::new(from) {
+        // A file that can't be read would be missing from the copy without anyone noticing
+        let entry = entry.map_err(|e| format!("Cannot read {}: {}", e.path().unwrap_or(from).display(), e))?;
+        let path = entry.path();
+        if entry.path_is_symlink() {
+            return Err(format!("Refusing to copy {}, because it is a symlink", path.display()));
+        }
+        if !entry.file_type().is_file() { continue; }
+        let relative_path = pathdiff::diff_paths(path, from).unwrap();
+        // Make sure the file cannot end up outside of the output folder
+        let output_path = archive::safe_join(to, &relative_path.to_string_lossy())
+            .map_err(|e| format!("Refusing to copy {}: {}", path.display(), e))?;
+        let replaces = output_path.exists();
+        if replaces {
+            match existing_files {
+                ExistingFiles::Skip => {
+                    report.skipped_files += 1;
+                    kept.push(relative_path);
+                    continue;
+                },
+                ExistingFiles::ReplaceChanged if is_same_file(path, &output_path) => {
+                    report.unchanged_files += 1;
+                    kept.push(relative_path);
+                    continue;
+                },
+                ExistingFiles::ReplaceChanged => {}
+            }
+        }
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        copies.push(PlannedCopy { from: path.to_path_buf(), to: output_path, relative_path, size, replaces });
+    }
+    Ok(SyncPlan { copies, kept, report })
+}
+
+/// Finds the files of the source that are missing or changed in the destination, without copying anything
+pub async fn plan_sync(from : &Path, to : &Path, existing_files : ExistingFiles) -> Result<SyncPlan, String> {
+    let (from_path, to_path) = (from.to_path_buf(), to.to_path_buf());
+    run_blocking(move || plan(&from_path, &to_path, existing_files)).await
+}
+
+/// Copies a file while counting the copied bytes. The hash is calculated on the way, so neither file has to be read again.
+fn copy_file(copy : &PlannedCopy, copied_bytes : &AtomicU64) -> Result<(), String> {
+    if let Some(parent_dir) = copy.to.parent() {
+        std::fs::create_dir_all(parent_dir).or(Err(format!("Cannot create {}", parent_dir.display())))?;
+    }
+    let copy_contents = || -> std::io::Result<String> {
+        let mut reader = BufReader::new(File::open(&copy.from)?);
+        let mut writer = BufWriter::new(File::create(&copy.to)?);
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; COPY_BUFFER_SIZE];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 { break; }
+            writer.write_all(&buffer[..read])?;
+            hasher.update(&buffer[..read]);
+            copied_bytes.fetch_add(read as u64, Ordering::Relaxed);
+        }
+        writer.flush()?;
+        Ok(format!("{:x}", hasher.finalize()))
+    };
+    let sha256 = copy_contents().map_err(|e| format!("Cannot copy {} to {}: {}", copy.from.display(), copy.to.display(), e))?;
+    remember_hash(&copy.from, &sha256);
+    remember_hash(&copy.to, &sha256);
+    Ok(())
+}
+
+/// Copies the files of a plan, so the destination contains every file of the source.
+/// The copies run in parallel on the blocking thread pool and their progress is reported in bytes.
+pub async fn run_sync(plan : SyncPlan, label : &str, window : &Window) -> Result<SyncReport, String> {
+    let SyncPlan { copies, mut report, .. } = plan;
+    report.replaced_files = copies.iter().filter(|c| c.replaces).map(|c| c.relative_path.clone()).collect();
+    let total_bytes : u64 = copies.iter().map(|c| c.size).sum();
+    let total_files = copies.len() as u64;
+    let copied_bytes = Arc::new(AtomicU64::new(0));
+    let copied_files = Arc::new(AtomicU64::new(0));
+    let done = Arc::new(AtomicBool::new(false));
+    let get_progress = {
+        let (label, copied_bytes, copied_files) = (label.to_string(), copied_bytes.clone(), copied_files.clone());
+        move || SyncProgress {
+            label: label.clone(),
+            copied_bytes: copied_bytes.load(Ordering::Relaxed),
+            total_bytes,
+            copied_files: copied_files.load(Ordering::Relaxed),
+            total_files
+        }
+    };
+    let emit_progress = {
+        let window = window.clone();
+        move |progress : SyncProgress| {
+            let percent = progress.copied_bytes as f64 / total_bytes.max(1) as f64 * 100.0;
+            window.emit("progress", format!("{} {:.1}%", progress.label, percent)).unwrap();
+            window.emit("sync_progress", progress).unwrap();
+        }
+    };
+    let reporter = {
+        let (done, get_progress, emit_progress) = (done.clone(), get_progress.clone(), emit_progress.clone());
+        tokio::spawn(async move {
+            while !done.load(Ordering::Relaxed) {
+                emit_progress(get_progress());
+                tokio::time::sleep(tokio::time::Duration::from_millis(PROGRESS_INTERVAL_MS)).await;
+            }
+        })
+    };
+    let semaphore = Arc::new(Semaphore::new(MAX_PARALLEL_COPIES));
+    let mut tasks = vec![];
+    for copy in copies {
+        let permit = semaphore.clone().acquire_owned().await.map_err(|e| e.to_string())?;
+        let (copied_bytes, copied_files) = (copied_bytes.clone(), copied_files.clone());
+        tasks.push(tokio::task::spawn_blocking(move || {
+            let result = copy_file(&copy, &copied_bytes);
+            copied_files.fetch_add(1, Ordering::Relaxed);
+            drop(permit);
+            result
+        }));
+    }
+    let mut first_error = None;
+    for task in tasks {
+        let result = task.await.map_err(|e| format!("A background task failed: {}", e)).and_then(|r| r);
+        if let Err(e) = result {
+            log_error!("{}", e);
+            first_error.get_or_insert(e);
+        }
+    }
+    done.store(true, Ordering::Relaxed);
+    let _ = reporter.await;
+    emit_progress(get_progress());
+    run_blocking(|| { save_hash_cache(); Ok(()) }).await?;
+    if let Some(error) = first_error {
+        return Err(error);
+    }
+    report.copied_files = total_files;
+    report.copied_bytes = copied_bytes.load(Ordering::Relaxed);
+    log_info!(
+        "Synced {}: copied {} files ({} bytes), {} replaced, {} unchanged, {} skipped",
+        label, report.copied_files, report.copied_bytes, report.replaced_files.len(), report.unchanged_files, report.skipped_files
+    );
+    Ok(report)
+}
@@ -1,32 +1,16 @@
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
 use reqwest::Client;
 use tauri::Window;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use futures_util::StreamExt;
+use sysinfo::{System, SystemExt};
+use sha2::{Digest, Sha256};
 use crate::config::Config;
 use crate::KnownMod;
 
 const KNOWN_MODS_GITHUB_URL : &'static str = "https://raw.githubusercontent.com/RedstoneMedia/SussyLauncher/master/src-tauri/known_mods.json";
 
-pub fn copy_folder(from : &Path, to : &Path) {
-    for entry in WalkDir::new(from).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if path.is_dir() {continue}
-        let relative_path = pathdiff::diff_paths(path, from).unwrap();
-        let output_path = Path::new(to).join(relative_path);
-        if let Some(parent_dir) = output_path.parent() {
-            std::fs::create_dir_all(parent_dir).unwrap();
-        }
-        if output_path.exists() {continue} // Don't overwrite
-        match std::fs::copy(path, output_path) {
-          Ok(_) => {},
-          Err(e) => {eprintln!("cannot copy {} to {} Error: {}", path.display(), to.display(), e)}
-        }
-    }
-}
-
 pub fn get_reqwest_client() -> Client {
     reqwest::ClientBuilder::new()
         .user_agent("SussyLauncher")
@@ -47,12 +31,12 @@ pub async fn load_known_mods() -> Vec<KnownMod> {
                     if r.status().is_success() {
                         r.json().await.unwrap_or(vec![])
                     } else {
-                        eprintln!("Got Status code {}, when attempting to request known mods", r.status());
+                        log_error!("Got Status code {}, when attempting to request known mods", r.status());
                         vec![]
                     }
                 },
                 Err(e) => {
-                    eprintln!("Cannot get known mods: {}", e);
+                    log_error!("Cannot get known mods: {}", e);
                     vec![]
                 }
             }
@@ -86,3 +70,17 @@ pub async fn download_file(client: &Client, download_url: &str, output_file_path
 pub fn get_plugins_path(config : &Config) -> PathBuf {
     Path::new(&config.among_us_path).join(Path::new("BepInEx/plugins"))
 }
+
+pub fn is_among_us_running() -> bool {
+    let mut sys = System::new();
+    sys.refresh_processes();
+    sys.processes_by_name("Among Us").count() > 0
+}
+
+/// Returns the SHA-256 hash of a file as a hex string
+pub fn hash_file(path : &Path) -> Result<String, String> {
+    let mut file = std::fs::File::open(path).or(Err(format!("Cannot open {}", path.display())))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).or(Err(format!("Cannot read {}", path.display())))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use crate::pe::{read_u16, read_u32, PeFile, IMAGE_DIRECTORY_ENTRY_RESOURCE};
+
+const RT_VERSION : u32 = 16;
+const VS_FIXEDFILEINFO_SIGNATURE : u32 = 0xFEEF04BD;
+/// Version resources have four levels: the root, `StringFileInfo`, the string tables and the strings.
+/// Anything much deeper is malformed and would only waste stack.
+const MAX_BLOCK_DEPTH : usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Version(pub u16, pub u16, pub u16, pub u16);
+
+impl Version {
+    fn from_dwords(most_significant : u32, least_significant : u32) -> Self {
+        Self(
+            (most_significant >> 16) as u16,
+            most_significant as u16,
+            (least_significant >> 16) as u16,
+            least_significant as u16
+        )
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.0, self.1, self.2, self.3)
+    }
+}
+
+/// Contents of the `VS_VERSIONINFO` resource of a PE file
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionInfo {
+    pub file_version : Option<Version>,
+    pub product_version : Option<Version>,
+    /// Strings like `ProductName`, `FileDescription` or `ProductVersion` from the `StringFileInfo` block
+    pub strings : HashMap<String, String>
+}
+
+/// A node of the version resource. All of them share the same header, but differ in their value and children.
+struct VersionBlock<'a> {
+    key : String,
+    value : &'a [u8],
+    is_text : bool,
+    children : Vec<VersionBlock<'a>>
+}
+
+fn align_4(offset : usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn read_utf16_string(data : &[u8], offset : usize) -> Option<(String, usize)> {
+    let mut units = Vec::new();
+    let mut position = offset;
+    loop {
+        let unit = read_u16(data, position)?;
+        position += 2;
+        if unit == 0 { break; }
+        units.push(unit);
+    }
+    Some((String::from_utf16_lossy(&units), position))
+}
+
+/// Parses a block starting at `offset`. Offsets are relative to the start of the resource, which is 4 byte aligned.
+/// Children only get the data of their parent, so every level is smaller than the one above it.
+fn parse_block(data : &[u8], offset : usize, depth : usize) -> Option<(VersionBlock<'_>, usize)> {
+    if depth > MAX_BLOCK_DEPTH { return None; }
+    let length = read_u16(data, offset)? as usize;
+    let value_length = read_u16(data, offset + 2)? as usize;
+    let is_text = read_u16(data, offset + 4)? == 1;
+    let end = offset + length;
+    if length < 6 || end > data.len() { return None; }
+    let (key, key_end) = read_utf16_string(&data[..end], offset + 6)?;
+    let value_start = align_4(key_end);
+    // The length of text values is given in characters instead of bytes
+    let value_size = if is_text { value_length * 2 } else { value_length };
+    let value = data.get(value_start..(value_start + value_size).min(end)).unwrap_or(&[]);
+    let mut children = Vec::new();
+    let mut child_offset = align_4(value_start + value_size);
+    while child_offset + 6 <= end {
+        let Some((child, child_end)) = parse_block(&data[..end], child_offset, depth + 1) else { break; };
+        children.push(child);
+        child_offset = align_4(child_end);
+    }
+    Some((VersionBlock { key, value, is_text, children }, end))
+}
+
+/// Walks the three levels (type, name, language) of the resource directory to the first resource of the given type
+fn find_resource<'a>(pe_file : &PeFile<'a>, resource_type : u32) -> Option<&'a [u8]> {
+    let resources = pe_file.get_directory_data(IMAGE_DIRECTORY_ENTRY_RESOURCE)?;
+    let mut directory_offset = 0;
+    for level in 0..3 {
+        let named_entries = read_u16(resources, directory_offset + 12)? as usize;
+        let id_entries = read_u16(resources, directory_offset + 14)? as usize;
+        let entries_offset = directory_offset + 16;
+        // Only the type is matched, for the name and language the first entry is good enough
+        let entry_offset = (0..named_entries + id_entries)
+            .map(|i| entries_offset + i * 8)
+            .find(|entry_offset| level != 0 || read_u32(resources, *entry_offset) == Some(resource_type))?;
+        let target = read_u32(resources, entry_offset + 4)?;
+        if target & 0x8000_0000 == 0 {
+            // Data entry, which contains an RVA instead of an offset into the resource section
+            let data_rva = read_u32(resources, target as usize)?;
+            let data_size = read_u32(resources, target as usize + 4)? as usize;
+            let data_offset = pe_file.rva_to_offset(data_rva)?;
+            return pe_file.data.get(data_offset..data_offset.checked_add(data_size)?);
+        }
+        directory_offset = (target & 0x7FFF_FFFF) as usize;
+    }
+    None
+}
+
+impl VersionInfo {
+    /// Reads the version resource of a PE file, without relying on any OS APIs
+    pub fn read(data : &[u8]) -> Result<Self, String> {
+        let pe_file = PeFile::parse(data)?;
+        let resource = find_resource(&pe_file, RT_VERSION).ok_or("The file has no version resource")?;
+        let (root, _) = parse_block(resource, 0, 0).ok_or("The version resource is invalid")?;
+        if root.key != "VS_VERSION_INFO" {
+            return Err(format!("The version resource has the unexpected key {}", root.key));
+        }
+        let mut version_info = Self { file_version: None, product_version: None, strings: HashMap::new() };
+        if read_u32(root.value, 0) == Some(VS_FIXEDFILEINFO_SIGNATURE) {
+            let read = |offset| read_u32(root.value, offset).unwrap_or(0);
+            version_info.file_version = Some(Version::from_dwords(read(8), read(12)));
+            version_info.product_version = Some(Version::from_dwords(read(16), read(20)));
+        }
+        let string_tables = root.children.iter()
+            .filter(|c| c.key == "StringFileInfo")
+            .flat_map(|c| c.children.iter());
+        for string in string_tables.flat_map(|t| t.children.iter()) {
+            if !string.is_text { continue; }
+            let units : Vec<u16> = string.value.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+            let value = String::from_utf16_lossy(&units).trim_end_matches('\0').to_string();
+            version_info.strings.entry(string.key.clone()).or_insert(value);
+        }
+        Ok(version_info)
+    }
+
+    pub fn read_file(path : &std::path::Path) -> Result<Self, String> {
+        let data = std::fs::read(path).or(Err(format!("Cannot read {}", path.display())))?;
+        Self::read(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::tests::read_fixture;
+
+    #[test]
+    fn reads_the_version_of_a_native_dll() {
+        let version_info = VersionInfo::read(&read_fixture("native.dll")).unwrap();
+        assert_eq!(version_info.file_version, Some(Version(1, 2, 3, 4)));
+        assert_eq!(version_info.product_version, Some(Version(1, 2, 0, 0)));
+        assert_eq!(version_info.strings.get("FileVersion").map(|s| s.as_str()), Some("1.2.3.4"));
+        assert_eq!(version_info.strings.get("ProductVersion").map(|s| s.as_str()), Some("1.2"));
+        assert_eq!(version_info.strings.get("ProductName").map(|s| s.as_str()), Some("Example Native"));
+        // Translation is a value of VarFileInfo and not a string
+        assert_eq!(version_info.strings.len(), 5);
+    }
+
+    #[test]
+    fn reads_the_version_of_a_managed_dll() {
+        let version_info = VersionInfo::read(&read_fixture("managed.dll")).unwrap();
+        assert_eq!(version_info.file_version.map(|v| v.to_string()), Some("1.2.3.0".to_string()));
+        assert_eq!(version_info.strings.get("FileVersion").map(|s| s.as_str()), Some("1.2.3.0"));
+        assert_eq!(version_info.strings.get("ProductVersion").map(|s| s.as_str()), Some("1.2.3+abcdef0"));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let native = read_fixture("native.dll");
+        let product_name : Vec<u8> = "ProductName".encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        let product_name_offset = native.windows(product_name.len()).position(|w| w == product_name).unwrap();
+        assert!(VersionInfo::read(&native[..0x100]).is_err());
+        // The resource section is cut off in the middle of the strings
+        assert_eq!(VersionInfo::read(&native[..product_name_offset]).err().unwrap(), "The file has no version resource");
+        assert!(VersionInfo::read(&[]).is_err());
+    }
+
+    #[test]
+    fn stops_at_blocks_that_overflow_their_parent() {
+        // A root block that claims more data than there is
+        let mut resource = vec![0xFF, 0x00, 0x00, 0x00, 0x00, 0x00];
+        resource.extend("VS_VERSION_INFO\0".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        assert!(parse_block(&resource, 0, 0).is_none());
+        assert!(parse_block(&resource, 0, MAX_BLOCK_DEPTH + 1).is_none());
+    }
+}
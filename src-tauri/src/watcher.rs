@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tauri::Window;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use walkdir::WalkDir;
+use crate::{session, GlobalConfig};
+use crate::mod_manager::ModLocation;
+
+const POLL_INTERVAL_MS : u64 = 1000;
+
+static WATCHERS : Lazy<Mutex<HashMap<String, (PathBuf, JoinHandle<()>)>>> = Lazy::new(|| Default::default());
+
+#[derive(Debug, Clone, Serialize)]
+pub enum DeployStatus {
+    /// A change was detected, but the game is running so the deploy waits until it exits
+    Queued,
+    Deployed,
+    Failed(String)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeployEvent {
+    pub name : String,
+    pub status : DeployStatus
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchedMod {
+    pub name : String,
+    pub path : String
+}
+
+/// Returns the newest modification time of a file, or of any file inside a folder
+fn get_last_modified(path : &Path) -> Option<SystemTime> {
+    if path.is_file() {
+        return path.metadata().and_then(|m| m.modified()).ok();
+    }
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok().and_then(|m| m.modified().ok()))
+        .max()
+}
+
+fn emit_deploy_event(window : &Window, name : &str, status : DeployStatus) {
+    window.emit("deploy", DeployEvent { name: name.to_string(), status }).unwrap();
+}
+
+async fn deploy(name : &str, config : &GlobalConfig, window : &Window) -> Result<(), String> {
+    let mut config = config.lock().await;
+    let Some(index) = config.downloaded.iter().position(|m| m.name == name) else {
+        return Err(format!("{} does not exist anymore", name));
+    };
+    let mut modification = std::mem::take(&mut config.downloaded[index]);
+    let result = modification.redeploy(&config, window).await;
+    config.downloaded[index] = modification;
+    config.save();
+    result
+}
+
+async fn watch_loop(name : String, watch_path : PathBuf, config : GlobalConfig, window : Window) {
+    let mut last_modified = get_last_modified(&watch_path);
+    let mut pending = false;
+    loop {
+        tokio::time::sleep(tokio::time::Duration::from_millis(POLL_INTERVAL_MS)).await;
+        let modified = get_last_modified(&watch_path);
+        if modified.is_some() && modified != last_modified {
+            last_modified = modified;
+            pending = true;
+            if session::is_game_running() {
+                emit_deploy_event(&window, &name, DeployStatus::Queued);
+            }
+        }
+        // Files in the plugins folder are locked while the game runs, so wait until it has exited
+        if !pending || session::is_game_running() { continue; }
+        pending = false;
+        match deploy(&name, &config, &window).await {
+            Ok(_) => emit_deploy_event(&window, &name, DeployStatus::Deployed),
+            Err(e) => emit_deploy_event(&window, &name, DeployStatus::Failed(e))
+        }
+    }
+}
+
+/// Starts watching a local mod and redeploys it into the game folder whenever its source changes.
+/// `watch_path` can be used to watch a different file or folder (e.g. a build output folder) than the mod location itself.
+pub async fn watch(name : String, watch_path : Option<String>, config : GlobalConfig, window : Window) -> Result<(), String> {
+    let location_path = {
+        let config = config.lock().await;
+        let Some(modification) = config.downloaded.iter().find(|m| m.name == name) else {
+            return Err(format!("{} does not exist", name));
+        };
+        match &modification.location {
+            ModLocation::Local(path) => path.clone(),
+            ModLocation::Github(_, _) => return Err(format!("Only local mods can be watched"))
+        }
+    };
+    let watch_path = PathBuf::from(watch_path.unwrap_or(location_path));
+    if !watch_path.exists() {
+        return Err(format!("{} does not exist", watch_path.display()));
+    }
+    let mut watchers = WATCHERS.lock().await;
+    if let Some((_, handle)) = watchers.remove(&name) {
+        handle.abort();
+    }
+    let handle = tokio::spawn(watch_loop(name.clone(), watch_path.clone(), config, window));
+    watchers.insert(name, (watch_path, handle));
+    Ok(())
+}
+
+pub async fn unwatch(name : &str) {
+    if let Some((_, handle)) = WATCHERS.lock().await.remove(name) {
+        handle.abort();
+    }
+}
+
+pub async fn get_watched() -> Vec<WatchedMod> {
+    WATCHERS.lock().await.iter().map(|(name, (path, _))| WatchedMod {
+        name: name.clone(),
+        path: path.display().to_string()
+    }).collect()
+}
@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use crate::{steam, AMONG_US_STEAM_ID};
+
+/// BepInEx is loaded by a fake `winhttp.dll` (doorstop), which Wine ignores unless the native dll is preferred
+pub const DOORSTOP_DLL_OVERRIDE : &'static str = "winhttp=n,b";
+
+pub fn find_in_path(name : &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).map(|p| p.join(name)).find(|p| p.is_file())
+}
+
+pub fn find_wine() -> Option<PathBuf> {
+    find_in_path("wine").or_else(|| find_in_path("wine64"))
+}
+
+/// Turns names like `Proton 8.0` or `GE-Proton7-49` into something that can be compared
+fn get_proton_version_key(name : &str) -> Vec<u32> {
+    name.split(|c : char| !c.is_ascii_digit())
+        .filter_map(|n| n.parse().ok())
+        .collect()
+}
+
+/// Finds the newest Proton, either shipped by Steam or a custom build in `compatibilitytools.d`
+pub fn find_proton() -> Option<PathBuf> {
+    let steam_root = steam::find_steam_root()?;
+    let mut proton_dirs : Vec<PathBuf> = vec![];
+    let mut add_dirs = |parent : &Path, only_proton : bool| {
+        let Ok(read_dir) = parent.read_dir() else { return; };
+        for entry in read_dir.filter_map(|e| e.ok()) {
+            let is_proton_name = entry.file_name().to_string_lossy().contains("Proton");
+            if (!only_proton || is_proton_name) && entry.path().join("proton").is_file() {
+                proton_dirs.push(entry.path());
+            }
+        }
+    };
+    add_dirs(&steam_root.join("compatibilitytools.d"), false);
+    for library_path in steam::get_library_folders(&steam_root) {
+        add_dirs(&library_path.join("steamapps").join("common"), true);
+    }
+    proton_dirs.into_iter()
+        .max_by_key(|d| get_proton_version_key(&d.file_name().unwrap_or_default().to_string_lossy()))
+        .map(|d| d.join("proton"))
+}
+
+/// The prefix Steam created for the game, so the same Wine prefix is used no matter how the game is started
+pub fn get_default_prefix() -> Option<PathBuf> {
+    steam::find_among_us().and_then(|i| i.proton_prefix)
+}
+
+/// Proton wants the folder that contains the prefix, not the prefix itself
+pub fn get_default_compat_data_path() -> Option<PathBuf> {
+    steam::find_among_us().map(|i| i.library_path.join("steamapps").join("compatdata").join(AMONG_US_STEAM_ID))
+}
+
+/// Adds the doorstop override, while keeping overrides set by the user
+pub fn add_dll_override(env : &mut BTreeMap<String, String>) {
+    match env.get_mut("WINEDLLOVERRIDES") {
+        Some(overrides) if overrides.contains("winhttp") => {},
+        Some(overrides) => *overrides = format!("{};{}", overrides, DOORSTOP_DLL_OVERRIDE),
+        None => {
+            env.insert("WINEDLLOVERRIDES".to_string(), DOORSTOP_DLL_OVERRIDE.to_string());
+        }
+    }
+}
@@ -0,0 +1,146 @@
+#!/usr/bin/env python3
+"""Writes the archives in archives/, which the tests in src/archive.rs extract.
+
+Every format gets three archives:
+- mod: a mod wrapped in a Mod-v1/ folder, with a README.txt outside of it
+- symlink: a plugin that is a symlink to /etc/passwd
+- traversal: a file named ../evil.dll
+
+7z and rar have no writer in the Python standard library, so they are written by hand with stored (uncompressed) entries.
+"""
+import io
+import os
+import struct
+import tarfile
+import zipfile
+import zlib
+
+OUTPUT_FOLDER = os.path.join(os.path.dirname(os.path.abspath(__file__)), "archives")
+S_IFREG = 0o100000
+S_IFDIR = 0o040000
+S_IFLNK = 0o120000
+FILE_ATTRIBUTE_ARCHIVE = 0x20
+FILE_ATTRIBUTE_UNIX_EXTENSION = 0x8000
+
+# (name, mode, data), where the data of a symlink is its target
+ARCHIVES = {
+    "mod": [
+        ("README.txt", S_IFREG | 0o644, b"Read me\n"),
+        ("Mod-v1/BepInEx/plugins/x.dll", S_IFREG | 0o644, b"not really a dll\n"),
+        ("Mod-v1/BepInEx/config/x.cfg", S_IFREG | 0o644, b"[General]\nEnabled = true\n"),
+    ],
+    "symlink": [
+        ("BepInEx/plugins/x.dll", S_IFREG | 0o644, b"not really a dll\n"),
+        ("BepInEx/plugins/link.dll", S_IFLNK | 0o777, b"/etc/passwd"),
+    ],
+    "traversal": [
+        ("BepInEx/plugins/x.dll", S_IFREG | 0o644, b"not really a dll\n"),
+        ("../evil.dll", S_IFREG | 0o644, b"evil\n"),
+    ],
+}
+
+
+def write_zip(path, entries):
+    with zipfile.ZipFile(path, "w") as archive:
+        for name, mode, data in entries:
+            info = zipfile.ZipInfo(name, (2023, 1, 1, 0, 0, 0))
+            info.create_system = 3
+            info.external_attr = mode << 16
+            archive.writestr(info, data)
+
+
+def write_tar_gz(path, entries):
+    buffer = io.BytesIO()
+    with tarfile.open(fileobj=buffer, mode="w", format=tarfile.GNU_FORMAT) as archive:
+        for name, mode, data in entries:
+            info = tarfile.TarInfo(name)
+            info.mtime = 1672531200
+            info.mode = mode & 0o7777
+            if mode & S_IFLNK == S_IFLNK:
+                info.type = tarfile.SYMTYPE
+                info.linkname = data.decode()
+                archive.addfile(info)
+            else:
+                info.size = len(data)
+                archive.addfile(info, io.BytesIO(data))
+    with open(path, "wb") as file:
+        # A fixed mtime keeps the output the same between runs
+        file.write(gzip_compress(buffer.getvalue()))
+
+
+def gzip_compress(data):
+    compressor = zlib.compressobj(9, zlib.DEFLATED, -15)
+    body = compressor.compress(data) + compressor.flush()
+    header = b"\x1f\x8b\x08\x00" + struct.pack("<I", 0) + b"\x02\xff"
+    return header + body + struct.pack("<II", zlib.crc32(data), len(data) & 0xFFFFFFFF)
+
+
+def encode_7z_number(value):
+    """7z numbers start with a byte, whose leading one bits tell how many bytes follow"""
+    for extra_bytes in range(8):
+        if value < (1 << (7 * (extra_bytes + 1))):
+            high_bits = value >> (8 * extra_bytes)
+            first_byte = (0xFF00 >> extra_bytes) & 0xFF | high_bits
+            return bytes([first_byte]) + value.to_bytes(8, "little")[:extra_bytes]
+    return b"\xff" + value.to_bytes(8, "little")
+
+
+def write_7z(path, entries):
+    count = len(entries)
+    packed = b"".join(data for _, _, data in entries)
+    number = encode_7z_number
+    header = b"\x01"  # Header
+    header += b"\x04"  # MainStreamsInfo
+    header += b"\x06" + number(0) + number(count)  # PackInfo
+    header += b"\x09" + b"".join(number(len(data)) for _, _, data in entries) + b"\x00"
+    header += b"\x07" + b"\x0b" + number(count) + b"\x00"  # UnpackInfo with one folder per file
+    header += b"\x01\x01\x00" * count  # One coder, with the one byte id of Copy
+    header += b"\x0c" + b"".join(number(len(data)) for _, _, data in entries) + b"\x00"
+    header += b"\x08" + b"\x0a\x01" + b"".join(struct.pack("<I", zlib.crc32(data)) for _, _, data in entries) + b"\x00"
+    header += b"\x00"
+    header += b"\x05" + number(count)  # FilesInfo
+    names = b"\x00" + b"".join(name.encode("utf-16-le") + b"\x00\x00" for name, _, _ in entries)
+    header += b"\x11" + number(len(names)) + names
+    attributes = b"\x01\x00" + b"".join(
+        struct.pack("<I", FILE_ATTRIBUTE_ARCHIVE | FILE_ATTRIBUTE_UNIX_EXTENSION | (mode << 16)) for _, mode, _ in entries
+    )
+    header += b"\x15" + number(len(attributes)) + attributes
+    header += b"\x00\x00"
+    start_header = struct.pack("<QQI", len(packed), len(header), zlib.crc32(header))
+    with open(path, "wb") as file:
+        file.write(b"7z\xbc\xaf\x27\x1c\x00\x04" + struct.pack("<I", zlib.crc32(start_header)) + start_header)
+        file.write(packed)
+        file.write(header)
+
+
+def rar_block(block_type, flags, body):
+    """Blocks of rar 1.5 to 4 start with the lower half of the CRC32 over the rest of the header"""
+    header = struct.pack("<BHH", block_type, flags, 7 + len(body)) + body
+    return struct.pack("<H", zlib.crc32(header) & 0xFFFF) + header
+
+
+def write_rar(path, entries):
+    host_os_unix = 3
+    dos_time = (43 << 25) | (1 << 21) | (1 << 16)
+    output = b"Rar!\x1a\x07\x00"
+    output += rar_block(0x73, 0, b"\x00" * 6)
+    for name, mode, data in entries:
+        name_bytes = name.encode()
+        body = struct.pack(
+            "<IIBIIBBHI",
+            len(data), len(data), host_os_unix, zlib.crc32(data), dos_time, 29, 0x30, len(name_bytes), mode
+        ) + name_bytes
+        # 0x8000 says the packed data follows the header
+        output += rar_block(0x74, 0x8000, body) + data
+    output += rar_block(0x7B, 0x4000, b"")
+    with open(path, "wb") as file:
+        file.write(output)
+
+
+WRITERS = {"zip": write_zip, "tar.gz": write_tar_gz, "7z": write_7z, "rar": write_rar}
+
+if __name__ == "__main__":
+    os.makedirs(OUTPUT_FOLDER, exist_ok=True)
+    for archive_name, entries in ARCHIVES.items():
+        for extension, write in WRITERS.items():
+            write(os.path.join(OUTPUT_FOLDER, "{}.{}".format(archive_name, extension)), entries)
@@ -0,0 +1,252 @@
+#!/usr/bin/env python3
+"""Writes the dlls in dlls/, which the tests in src/pe.rs, src/version_info.rs and src/dotnet.rs parse.
+
+- native.dll: a 64 bit native dll with a version resource and no code
+- managed.dll: a 32 bit .NET assembly, whose ExamplePlugin class has a [BepInPlugin] attribute.
+  The assembly also has a [TargetFramework] attribute like every SDK build, which must not be taken for a plugin.
+
+Neither needs a compiler, the headers, metadata tables and resources are written by hand as described in the
+PE format documentation and ECMA-335 partition II. They contain no code, so they only load as data.
+"""
+import os
+import struct
+
+OUTPUT_FOLDER = os.path.join(os.path.dirname(os.path.abspath(__file__)), "dlls")
+FILE_ALIGNMENT = 0x200
+SECTION_ALIGNMENT = 0x1000
+IMAGE_DIRECTORY_ENTRY_RESOURCE = 2
+IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR = 14
+IMAGE_SCN_CNT_CODE = 0x20
+IMAGE_SCN_CNT_INITIALIZED_DATA = 0x40
+IMAGE_SCN_MEM_EXECUTE = 0x20000000
+IMAGE_SCN_MEM_READ = 0x40000000
+RT_VERSION = 16
+
+
+def align(value, alignment):
+    return (value + alignment - 1) // alignment * alignment
+
+
+def pad(data, alignment):
+    return data + b"\0" * (align(len(data), alignment) - len(data))
+
+
+# Version resource
+
+def utf16(text):
+    return text.encode("utf-16-le") + b"\0\0"
+
+
+def version_block(key, value=b"", is_text=False, children=(), value_length=None):
+    """A node of VS_VERSIONINFO, the length of text values counts characters instead of bytes"""
+    data = pad(struct.pack("<HHH", 0, 0, 0) + utf16(key), 4) + value
+    for child in children:
+        data = pad(data, 4) + child
+    if value_length is None:
+        value_length = len(value) // 2 if is_text else len(value)
+    return struct.pack("<HHH", len(data), value_length, 1 if is_text else 0) + data[6:]
+
+
+def version_dwords(version):
+    major, minor, build, revision = version
+    return (major << 16) | minor, (build << 16) | revision
+
+
+def version_info(file_version, product_version, strings):
+    fixed_file_info = struct.pack(
+        "<13I",
+        0xFEEF04BD, 0x00010000,
+        *version_dwords(file_version),
+        *version_dwords(product_version),
+        0x3F, 0, 0x00040004, 2, 0, 0, 0
+    )
+    string_table = version_block("040904b0", children=[version_block(k, utf16(v), True) for k, v in strings])
+    var_file_info = version_block("VarFileInfo", children=[version_block("Translation", struct.pack("<HH", 0x0409, 0x04B0))])
+    return version_block("VS_VERSION_INFO", fixed_file_info, children=[version_block("StringFileInfo", children=[string_table]), var_file_info])
+
+
+def resource_section(rva, version_resource):
+    """A resource directory with the type, name and language levels, leading to the version resource"""
+    data = b""
+    for level, entry_id in enumerate([RT_VERSION, 1, 0x0409]):
+        next_offset = len(data) + 24
+        # The last level points to a data entry instead of another directory
+        target = next_offset if level == 2 else 0x80000000 | next_offset
+        data += struct.pack("<IIHHHH", 0, 0, 0, 0, 0, 1) + struct.pack("<II", entry_id, target)
+    data_entry_offset = len(data)
+    data += struct.pack("<IIII", rva + data_entry_offset + 16, len(version_resource), 0, 0)
+    return data + version_resource
+
+
+# CLI metadata
+
+class Heap:
+    def __init__(self, initial):
+        self.data = bytearray(initial)
+        self.offsets = {}
+
+    def add(self, value):
+        if value not in self.offsets:
+            self.offsets[value] = len(self.data)
+            self.data += value
+        return self.offsets[value]
+
+
+def compressed_length(length):
+    if length < 0x80:
+        return struct.pack("B", length)
+    return struct.pack(">H", 0x8000 | length)
+
+
+def ser_string(text):
+    data = text.encode("utf-8")
+    return compressed_length(len(data)) + data
+
+
+def metadata():
+    strings = Heap(b"\0")
+    blobs = Heap(b"\0")
+    string = lambda text: strings.add(text.encode("utf-8") + b"\0") if text else 0
+    blob = lambda data: blobs.add(compressed_length(len(data)) + data)
+    guid = bytes(range(0x10, 0x20))
+    # ResolutionScope tag 2 is AssemblyRef, MemberRefParent tag 1 is TypeRef,
+    # HasCustomAttribute tags 3 and 14 are TypeDef and Assembly, CustomAttributeType tag 3 is MemberRef
+    tables = [
+        (0x00, [struct.pack("<HHHHH", 0, string("ExamplePlugin.dll"), 1, 0, 0)]),
+        (0x01, [
+            struct.pack("<HHH", (1 << 2) | 2, string("BepInPlugin"), string("BepInEx")),
+            struct.pack("<HHH", (2 << 2) | 2, string("TargetFrameworkAttribute"), string("System.Runtime.Versioning")),
+        ]),
+        (0x02, [
+            struct.pack("<IHHHHH", 0, string("<Module>"), 0, 0, 1, 1),
+            struct.pack("<IHHHHH", 0x00100001, string("ExamplePlugin"), string("ExampleMod"), 0, 1, 1),
+        ]),
+        (0x0A, [
+            struct.pack("<HHH", (1 << 3) | 1, string(".ctor"), blob(bytes([0x20, 3, 0x01, 0x0E, 0x0E, 0x0E]))),
+            struct.pack("<HHH", (2 << 3) | 1, string(".ctor"), blob(bytes([0x20, 1, 0x01, 0x0E]))),
+        ]),
+        # Sorted by parent, so the attribute of the assembly comes first
+        (0x0C, [
+            struct.pack("<HHH", (1 << 5) | 14, (2 << 3) | 3, blob(b"\x01\x00" + ser_string(".NETStandard,Version=v2.1") + b"\x00\x00")),
+            struct.pack("<HHH", (2 << 5) | 3, (1 << 3) | 3, blob(
+                b"\x01\x00" + ser_string("com.example.plugin") + ser_string("Example Plugin") + ser_string("1.2.3") + b"\x00\x00"
+            )),
+        ]),
+        (0x20, [struct.pack("<IHHHHIHHH", 0x8004, 1, 2, 3, 0, 0, 0, string("ExamplePlugin"), 0)]),
+        (0x23, [
+            struct.pack("<HHHHIHHHH", 6, 0, 0, 0, 0, 0, string("BepInEx.Core"), 0, 0),
+            struct.pack("<HHHHIHHHH", 2, 1, 0, 0, 0, blob(bytes.fromhex("cc7b13ffcd2ddd51")), string("netstandard"), 0, 0),
+        ]),
+    ]
+    valid = sum(1 << table for table, _ in tables)
+    tables_stream = struct.pack("<IBBBBQQ", 0, 2, 0, 0, 1, valid, 0x000016003301FA00)
+    tables_stream += b"".join(struct.pack("<I", len(rows)) for _, rows in tables)
+    tables_stream += b"".join(b"".join(rows) for _, rows in tables)
+    streams = [
+        (b"#~", pad(tables_stream, 4)),
+        (b"#Strings", pad(bytes(strings.data), 4)),
+        (b"#US", pad(b"\0", 4)),
+        (b"#GUID", guid),
+        (b"#Blob", pad(bytes(blobs.data), 4)),
+    ]
+    version = pad(b"v4.0.30319\0", 4)
+    header_size = 16 + len(version) + 4 + sum(8 + align(len(name) + 1, 4) for name, _ in streams)
+    header = struct.pack("<IHHII", 0x424A5342, 1, 1, 0, len(version)) + version + struct.pack("<HH", 0, len(streams))
+    offset = header_size
+    for name, stream in streams:
+        header += struct.pack("<II", offset, len(stream)) + pad(name + b"\0", 4)
+        offset += len(stream)
+    return header + b"".join(stream for _, stream in streams)
+
+
+def cli_header(metadata_rva, metadata_size):
+    # Runtime 2.5, ILONLY, no entry point, strong name signature or any of the other directories
+    return struct.pack("<IHHIII", 72, 2, 5, metadata_rva, metadata_size, 1) + b"\0" * 52
+
+
+# PE file
+
+def pe_file(is_64_bit, sections, data_directories):
+    """sections are (name, characteristics, data), which are mapped one after the other from the RVA SECTION_ALIGNMENT"""
+    optional_header_size = 240 if is_64_bit else 224
+    headers_size = align(0x80 + 24 + optional_header_size + 40 * len(sections), FILE_ALIGNMENT)
+    section_headers = b""
+    section_data = b""
+    rva = SECTION_ALIGNMENT
+    for name, characteristics, data in sections:
+        raw_data = pad(data, FILE_ALIGNMENT)
+        section_headers += struct.pack(
+            "<8sIIIIIIHHI",
+            name, len(data), rva, len(raw_data), headers_size + len(section_data), 0, 0, 0, 0, characteristics
+        )
+        section_data += raw_data
+        rva += align(len(data), SECTION_ALIGNMENT)
+    image_size = rva
+    directories = [(0, 0)] * 16
+    for index, directory in data_directories.items():
+        directories[index] = directory
+    code_size = sum(len(pad(d, FILE_ALIGNMENT)) for _, c, d in sections if c & IMAGE_SCN_CNT_CODE)
+    data_size = sum(len(pad(d, FILE_ALIGNMENT)) for _, c, d in sections if not c & IMAGE_SCN_CNT_CODE)
+    if is_64_bit:
+        optional_header = struct.pack("<HBBIIIII", 0x20B, 14, 0, code_size, data_size, 0, 0, SECTION_ALIGNMENT)
+        optional_header += struct.pack("<Q", 0x180000000)
+    else:
+        optional_header = struct.pack("<HBBIIIIII", 0x10B, 48, 0, code_size, data_size, 0, 0, SECTION_ALIGNMENT, SECTION_ALIGNMENT)
+        optional_header += struct.pack("<I", 0x10000000)
+    optional_header += struct.pack("<IIHHHHHHIIIIHH", SECTION_ALIGNMENT, FILE_ALIGNMENT, 6, 0, 0, 0, 6, 0, 0, image_size, headers_size, 0, 3, 0x8160)
+    size_format = "<QQQQ" if is_64_bit else "<IIII"
+    optional_header += struct.pack(size_format, 0x100000, 0x1000, 0x100000, 0x1000)
+    optional_header += struct.pack("<II", 0, 16) + b"".join(struct.pack("<II", *d) for d in directories)
+    assert len(optional_header) == optional_header_size
+    dos_header = b"MZ" + b"\0" * 0x3A + struct.pack("<I", 0x80)
+    dos_header = dos_header.ljust(0x80, b"\0")
+    coff_header = struct.pack("<HHIIIHH", 0x8664 if is_64_bit else 0x14C, len(sections), 0, 0, 0, optional_header_size, 0x2022 if is_64_bit else 0x2102)
+    headers = dos_header + b"PE\0\0" + coff_header + optional_header + section_headers
+    return pad(headers, FILE_ALIGNMENT) + section_data
+
+
+def native_dll():
+    version_resource = version_info((1, 2, 3, 4), (1, 2, 0, 0), [
+        ("CompanyName", "Example"),
+        ("FileDescription", "Example native library"),
+        ("FileVersion", "1.2.3.4"),
+        ("ProductName", "Example Native"),
+        ("ProductVersion", "1.2"),
+    ])
+    resources = resource_section(2 * SECTION_ALIGNMENT, version_resource)
+    return pe_file(True, [
+        (b".text", IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ, b"\xC3"),
+        (b".rsrc", IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ, resources),
+    ], {IMAGE_DIRECTORY_ENTRY_RESOURCE: (2 * SECTION_ALIGNMENT, len(resources))})
+
+
+def managed_dll():
+    metadata_data = metadata()
+    text = cli_header(0x1048, len(metadata_data)) + metadata_data
+    version_resource = version_info((1, 2, 3, 0), (1, 2, 3, 0), [
+        ("FileDescription", "ExamplePlugin"),
+        ("FileVersion", "1.2.3.0"),
+        ("InternalName", "ExamplePlugin.dll"),
+        ("ProductName", "ExamplePlugin"),
+        ("ProductVersion", "1.2.3+abcdef0"),
+    ])
+    rsrc_rva = SECTION_ALIGNMENT + align(len(text), SECTION_ALIGNMENT)
+    resources = resource_section(rsrc_rva, version_resource)
+    return pe_file(False, [
+        (b".text", IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ, text),
+        (b".rsrc", IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ, resources),
+    ], {
+        IMAGE_DIRECTORY_ENTRY_RESOURCE: (rsrc_rva, len(resources)),
+        IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR: (SECTION_ALIGNMENT, 72),
+    })
+
+
+def main():
+    os.makedirs(OUTPUT_FOLDER, exist_ok=True)
+    for name, data in [("native.dll", native_dll()), ("managed.dll", managed_dll())]:
+        with open(os.path.join(OUTPUT_FOLDER, name), "wb") as file:
+            file.write(data)
+
+
+if __name__ == "__main__":
+    main()
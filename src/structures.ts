@@ -5,5 +5,39 @@ export interface Mod {
     location : Object,
     enabled : boolean,
     newest_version : string,
-    do_update : boolean
-}
\ No newline at end of file
+    do_update : boolean,
+    subpath : string | null,
+    plugin_guids : string[],
+    install_policy : InstallPolicy,
+    installed_files : string[] | null
+}
+
+export type InstallPolicy = "Overwrite" | "Skip" | "BackupAndOverwrite"
+
+export interface InstallReport {
+    name : string,
+    overwritten : string[],
+    saved_originals : string[],
+    skipped : number
+}
+
+export type LaunchStrategy =
+    { type : "SteamUri" } |
+    { type : "EpicUri" } |
+    { type : "Executable" } |
+    { type : "Wine", wine_path : string | null, prefix : string | null } |
+    { type : "Proton", proton_path : string | null, compat_data_path : string | null } |
+    { type : "Custom", template : string }
+
+export interface LaunchSupport {
+    args : boolean,
+    env : boolean,
+    note : string | null
+}
+
+export interface LaunchOptions {
+    strategy : LaunchStrategy,
+    args : string[],
+    env : { [key : string] : string },
+    temporary_session : boolean
+}